@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::hint::black_box;
+use std::process;
+use std::time::{Duration, Instant};
+use yobmef::chess::Board;
+use yobmef::eval;
+use yobmef::movegen::{perft, MoveGen};
+use yobmef::search::Searcher;
+
+// Benchmarks for the hot paths, without a benchmark crate so it runs
+// offline. `cargo bench --bench bench` runs everything, add `-- movegen`
+// for only the ones with movegen in the name. Each result is a tab
+// separated line
+//
+//   name  iterations  ns/iter  items/s
+//
+// so two runs can be diffed, or pass `--baseline <file>` with the output
+// of an earlier run to get the change next to each line.
+
+const USAGE: &str = "usage: bench [filter] [--time <secs>] [--baseline <file>]";
+
+// Openings, middlegames and endgames, a bit of everything
+const POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bq1rk1/pp2nppp/2n1p3/3pP3/1b1P4/2NB1N2/PP3PPP/R1BQK2R w KQ - 0 9",
+    "6k1/5pp1/4p2p/8/2r5/5P2/5KPP/3R4 w - - 0 40",
+];
+
+struct Options {
+    filter: String,
+    time: Duration,
+    baseline: HashMap<String, f64>, // ns/iter by name
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut opts = Options {
+        filter: String::new(),
+        time: Duration::from_secs(1),
+        baseline: HashMap::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // cargo bench passes this to every bench binary
+            "--bench" => {}
+            "--time" => {
+                let secs: f64 = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .ok_or("--time needs a number of seconds")?;
+                opts.time = Duration::from_secs_f64(secs);
+            }
+            "--baseline" => {
+                let path = args.next().ok_or("--baseline needs a file")?;
+                opts.baseline = load_baseline(&path)?;
+            }
+            _ if opts.filter.is_empty() && !arg.starts_with("--") => opts.filter = arg,
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    Ok(opts)
+}
+
+fn load_baseline(path: &str) -> Result<HashMap<String, f64>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut baseline = HashMap::new();
+
+    for line in text.lines().filter(|l| !l.starts_with('#')) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if let [name, _, ns, ..] = fields[..] {
+            if let Ok(ns) = ns.parse() {
+                baseline.insert(name.to_string(), ns);
+            }
+        }
+    }
+
+    Ok(baseline)
+}
+
+struct Bench {
+    opts: Options,
+}
+
+impl Bench {
+    // Runs f until the time is up and prints how long one call took.
+    // f returns how many items it went through (nodes, moves, ...), for
+    // the rate.
+    fn run<F: FnMut() -> u64>(&self, name: &str, mut f: F) {
+        if !name.contains(&self.opts.filter) {
+            return;
+        }
+
+        // Once to warm up the caches (and the pawn hash table)
+        let items = f();
+
+        let mut iters = 0;
+        let start = Instant::now();
+        while iters == 0 || start.elapsed() < self.opts.time {
+            black_box(f());
+            iters += 1;
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+
+        let ns = elapsed * 1e9 / iters as f64;
+        let rate = items as f64 * iters as f64 / elapsed;
+        let mut line = format!("{}\t{}\t{:.0}\t{:.0}", name, iters, ns, rate);
+        if let Some(old) = self.opts.baseline.get(name) {
+            line += &format!("\t{:+.1}%", (ns - old) / old * 100.0);
+        }
+        println!("{}", line);
+    }
+}
+
+fn main() {
+    let opts = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let boards: Vec<Board> = POSITIONS
+        .iter()
+        .map(|fen| Board::from_fen(fen).unwrap())
+        .collect();
+    let bench = Bench { opts };
+
+    println!("# name\titerations\tns/iter\titems/s");
+
+    let start = Board::from_start_pos();
+    bench.run("perft/startpos/4", || perft(&start, 4));
+    bench.run("perft/kiwipete/3", || perft(&boards[1], 3));
+    bench.run("perft/endgame/5", || perft(&boards[2], 5));
+
+    bench.run("movegen/legal", || {
+        boards
+            .iter()
+            .map(|b| MoveGen::new_legal(b).count() as u64)
+            .sum()
+    });
+
+    // Every legal move of every position
+    let moves: Vec<(&Board, Vec<_>)> = boards
+        .iter()
+        .map(|b| (b, MoveGen::new_legal(b).collect()))
+        .collect();
+    bench.run("make_move", || {
+        let mut n = 0;
+        for (board, moves) in moves.iter() {
+            for mv in moves {
+                black_box(board.make_move(mv));
+                n += 1;
+            }
+        }
+        n
+    });
+
+    bench.run("eval/get_score", || {
+        for board in boards.iter() {
+            black_box(eval::get_score(board, false));
+        }
+        boards.len() as u64
+    });
+
+    // A fresh searcher for each position, so no run benefits from the
+    // table of the one before
+    for depth in [3, 4] {
+        bench.run(&format!("search/depth/{}", depth), || {
+            let mut nodes = 0;
+            for board in boards.iter() {
+                let mut searcher = Searcher::new();
+                searcher.search_depth(board, depth);
+                nodes += searcher.nodes;
+            }
+            nodes
+        });
+    }
+}
@@ -0,0 +1,249 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+use yobmef::chess::{Board, Color, Movement, Piece};
+use yobmef::eval::MATE;
+use yobmef::movegen::MoveGen;
+use yobmef::search::{SearchResult, Searcher};
+
+// Self-play data generation, for the tuner or for training a network.
+//
+// Plays games from random openings and writes every quiet position as
+// `<fen> | <score> | <result>`, score in centipawns and result 1.0/0.5/0.0,
+// both from white's point of view. Game n is always played with seed + n,
+// so the same seed gives the same games (and with one thread, the same file).
+
+const USAGE: &str = "usage: datagen --out <file> [--games <n>] [--depth <n> | --nodes <n>] \
+                     [--threads <n>] [--seed <n>] [--random-plies <n>]";
+
+// Adjudication, so we don't waste time on decided games
+const WIN_SCORE: i16 = 1000; // for WIN_PLIES plies in a row
+const WIN_PLIES: usize = 4;
+const DRAW_SCORE: i16 = 10; // for DRAW_PLIES plies in a row, after DRAW_MIN_PLY
+const DRAW_PLIES: usize = 10;
+const DRAW_MIN_PLY: usize = 80;
+const MAX_PLIES: usize = 400;
+
+struct Options {
+    out: String,
+    games: u64,
+    depth: Option<i16>,
+    nodes: Option<u64>,
+    threads: usize,
+    seed: u64,
+    random_plies: usize,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options {
+        out: String::new(),
+        games: 1000,
+        depth: None,
+        nodes: None,
+        threads: 1,
+        seed: 0,
+        random_plies: 8,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(format!("{} needs a value", arg))?;
+        let bad = |e: std::num::ParseIntError| format!("{}: {}", arg, e);
+
+        match arg.as_str() {
+            "--out" => opts.out = value,
+            "--games" => opts.games = value.parse().map_err(bad)?,
+            "--depth" => opts.depth = Some(value.parse().map_err(bad)?),
+            "--nodes" => opts.nodes = Some(value.parse().map_err(bad)?),
+            "--threads" => opts.threads = value.parse().map_err(bad)?,
+            "--seed" => opts.seed = value.parse().map_err(bad)?,
+            "--random-plies" => opts.random_plies = value.parse().map_err(bad)?,
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    if opts.out.is_empty() {
+        return Err("no output file".to_string());
+    }
+    if opts.depth.is_none() && opts.nodes.is_none() {
+        opts.nodes = Some(5000);
+    }
+    if opts.threads == 0 {
+        return Err("need at least one thread".to_string());
+    }
+
+    Ok(opts)
+}
+
+fn search(searcher: &mut Searcher, board: &Board, opts: &Options) -> SearchResult {
+    match (opts.depth, opts.nodes) {
+        (Some(depth), _) => searcher.search_depth(board, depth),
+        (None, Some(nodes)) => searcher.search_nodes(board, nodes),
+        (None, None) => unreachable!(),
+    }
+}
+
+// Random legal moves, starting over if we stumble into a finished game.
+fn random_opening(rng: &mut StdRng, plies: usize) -> Board {
+    'retry: loop {
+        let mut board = Board::from_start_pos();
+
+        for _ in 0..plies {
+            let moves: Vec<Movement> = MoveGen::new_legal(&board).collect();
+            match moves.choose(rng) {
+                Some(mv) => board.make_move_mut(mv),
+                None => continue 'retry,
+            }
+        }
+
+        if MoveGen::new_legal(&board).count() > 0 {
+            return board;
+        }
+    }
+}
+
+fn insufficient_material(board: &Board) -> bool {
+    let minors =
+        board.pieces(Piece::Knight).count_ones() + board.pieces(Piece::Bishop).count_ones();
+    let majors = board.pieces(Piece::Rook).count_ones() + board.pieces(Piece::Queen).count_ones();
+
+    board.pieces(Piece::Pawn).count_ones() == 0 && majors == 0 && minors <= 1
+}
+
+// Play one game, returns the quiet positions with their scores and the result.
+fn play_game(opts: &Options, game: u64) -> (Vec<(String, i16)>, f64) {
+    let mut rng = StdRng::seed_from_u64(opts.seed.wrapping_add(game));
+    let mut board = random_opening(&mut rng, opts.random_plies);
+    let mut searcher = Searcher::new();
+
+    let mut positions = Vec::new();
+    let mut seen: HashMap<Board, u8> = HashMap::new();
+    let mut win_plies = 0;
+    let mut draw_plies = 0;
+
+    for ply in 0..MAX_PLIES {
+        if MoveGen::new_legal(&board).count() == 0 {
+            let result = if board.in_check() {
+                // Side to move got mated
+                match board.side_to_move {
+                    Color::White => 0.0,
+                    Color::Black => 1.0,
+                }
+            } else {
+                0.5
+            };
+            return (positions, result);
+        }
+
+        let repetitions = seen.entry(board.clone()).or_insert(0);
+        *repetitions += 1;
+        if *repetitions >= 3 || insufficient_material(&board) {
+            return (positions, 0.5);
+        }
+
+        let sr = search(&mut searcher, &board, opts);
+        let score = sr.eval; // White's point of view
+
+        // Adjudicate
+        if score.abs() >= WIN_SCORE {
+            win_plies += 1;
+            if win_plies >= WIN_PLIES {
+                return (positions, if score > 0 { 1.0 } else { 0.0 });
+            }
+        } else {
+            win_plies = 0;
+        }
+        if ply >= DRAW_MIN_PLY && score.abs() <= DRAW_SCORE {
+            draw_plies += 1;
+            if draw_plies >= DRAW_PLIES {
+                return (positions, 0.5);
+            }
+        } else {
+            draw_plies = 0;
+        }
+
+        // Only quiet positions, the static eval can't judge the rest
+        let quiet = !board.in_check() && !sr.mv.is_capture() && !sr.mv.is_promotion();
+        if quiet && score.abs() < MATE - 1000 {
+            positions.push((board.to_fen(), score));
+        }
+
+        board.make_move_mut(&sr.mv);
+    }
+
+    (positions, 0.5)
+}
+
+fn main() {
+    let opts = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(1);
+    });
+
+    let file = File::create(&opts.out).unwrap_or_else(|e| {
+        eprintln!("failed to create {}: {}", opts.out, e);
+        process::exit(1);
+    });
+    let out = Mutex::new(BufWriter::new(file));
+
+    let next_game = AtomicU64::new(0);
+    let total_positions = AtomicU64::new(0);
+    let start = Instant::now();
+
+    let result: io::Result<()> = thread::scope(|s| {
+        let workers: Vec<_> = (0..opts.threads)
+            .map(|_| {
+                s.spawn(|| -> io::Result<()> {
+                    loop {
+                        let game = next_game.fetch_add(1, Ordering::Relaxed);
+                        if game >= opts.games {
+                            return Ok(());
+                        }
+
+                        let (positions, result) = play_game(&opts, game);
+
+                        let mut out = out.lock().unwrap();
+                        for (fen, score) in positions.iter() {
+                            writeln!(out, "{} | {} | {:.1}", fen, score, result)?;
+                        }
+
+                        let total = total_positions
+                            .fetch_add(positions.len() as u64, Ordering::Relaxed)
+                            + positions.len() as u64;
+                        if (game + 1).is_multiple_of(10) {
+                            eprintln!(
+                                "games {} positions {} ({:.0}/s)",
+                                game + 1,
+                                total,
+                                total as f64 / start.elapsed().as_secs_f64()
+                            );
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        workers.into_iter().try_for_each(|w| w.join().unwrap())
+    });
+
+    if let Err(e) = result.and_then(|_| out.lock().unwrap().flush()) {
+        eprintln!("failed to write {}: {}", opts.out, e);
+        process::exit(1);
+    }
+
+    eprintln!(
+        "wrote {} positions from {} games to {}",
+        total_positions.load(Ordering::Relaxed),
+        opts.games,
+        opts.out
+    );
+}
@@ -0,0 +1,191 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::process;
+use std::thread;
+use std::time::Instant;
+use yobmef::chess::{Board, Variant};
+use yobmef::movegen::{perft_divide, PerftTable};
+
+// Runs a perft suite in the usual EPD format, one position per line with
+// the expected node count for each depth:
+//
+//   rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902
+//
+// and reports every count that doesn't match.
+
+const USAGE: &str = "usage: perft <epd> [--depth <n>] [--threads <n>] [--hash <mb>] \
+                     [--variant <name>]";
+
+struct Options {
+    suite: String,
+    max_depth: u16,
+    threads: usize,
+    hash: usize, // megabytes, 0 for no table
+    variant: Variant,
+}
+
+struct Position {
+    line: usize,
+    fen: String,
+    board: Board,
+    depths: Vec<(u16, u64)>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut opts = Options {
+        suite: String::new(),
+        max_depth: u16::MAX,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        hash: 64,
+        variant: Variant::Standard,
+    };
+
+    let value = |args: &mut dyn Iterator<Item = String>, flag: &str| {
+        args.next().ok_or(format!("{} needs a value", flag))
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => {
+                opts.max_depth = value(&mut args, &arg)?
+                    .parse()
+                    .map_err(|e| format!("--depth: {}", e))?
+            }
+            "--threads" => {
+                opts.threads = value(&mut args, &arg)?
+                    .parse()
+                    .map_err(|e| format!("--threads: {}", e))?
+            }
+            "--hash" => {
+                opts.hash = value(&mut args, &arg)?
+                    .parse()
+                    .map_err(|e| format!("--hash: {}", e))?
+            }
+            "--variant" => {
+                let name = value(&mut args, &arg)?;
+                opts.variant = Variant::from_uci_name(&name)
+                    .ok_or_else(|| format!("unknown variant {}", name))?
+            }
+            _ if opts.suite.is_empty() && !arg.starts_with("--") => opts.suite = arg,
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    if opts.suite.is_empty() {
+        return Err("no perft suite".to_string());
+    }
+    if opts.max_depth == 0 {
+        return Err("--depth has to be at least 1".to_string());
+    }
+    if opts.threads == 0 {
+        return Err("need at least one thread".to_string());
+    }
+
+    Ok(opts)
+}
+
+fn load_suite(path: &str, variant: Variant) -> Result<Vec<Position>, Box<dyn Error>> {
+    let mut positions = Vec::new();
+
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split(';');
+        let fen = fields.next().unwrap_or("").trim();
+        let mut board =
+            Board::from_fen(fen).map_err(|e| format!("line {}: bad fen {}: {}", i + 1, fen, e))?;
+        board.set_variant(variant);
+
+        let mut depths = Vec::new();
+        for field in fields {
+            let bad = || format!("line {}: expected `D<depth> <nodes>`, got {}", i + 1, field);
+            let mut words = field.split_whitespace();
+            let depth = words
+                .next()
+                .and_then(|d| d.strip_prefix('D'))
+                .and_then(|d| d.parse().ok())
+                .ok_or_else(bad)?;
+            if depth == 0 {
+                return Err(format!("line {}: depth 0 has nothing to check", i + 1).into());
+            }
+            let nodes = words.next().and_then(|n| n.parse().ok()).ok_or_else(bad)?;
+            depths.push((depth, nodes));
+        }
+
+        positions.push(Position {
+            line: i + 1,
+            fen: fen.to_string(),
+            board,
+            depths,
+        });
+    }
+
+    Ok(positions)
+}
+
+fn main() {
+    let opts = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let positions = load_suite(&opts.suite, opts.variant).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let table = if opts.hash > 0 {
+        Some(PerftTable::new(opts.hash))
+    } else {
+        None
+    };
+
+    let start = Instant::now();
+    let mut nodes = 0;
+    let mut mismatches = 0;
+
+    for (i, position) in positions.iter().enumerate() {
+        println!("{}/{} {}", i + 1, positions.len(), position.fen);
+
+        for &(depth, want) in position.depths.iter() {
+            if depth > opts.max_depth {
+                continue;
+            }
+
+            let got: u64 = perft_divide(&position.board, depth, opts.threads, table.as_ref())
+                .iter()
+                .map(|(_, n)| n)
+                .sum();
+            nodes += got;
+
+            if got == want {
+                println!("  D{} {} ok", depth, got);
+            } else {
+                mismatches += 1;
+                println!(
+                    "  D{} {} MISMATCH want {} (line {})",
+                    depth, got, want, position.line
+                );
+            }
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "\n{} positions, {} mismatches, {} nodes in {:.2}s ({:.0} nodes/s)",
+        positions.len(),
+        mismatches,
+        nodes,
+        elapsed,
+        nodes as f64 / elapsed.max(1e-9)
+    );
+
+    if mismatches > 0 {
+        process::exit(1);
+    }
+}
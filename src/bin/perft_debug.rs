@@ -1,135 +1,468 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error;
+use std::fs;
 use std::io::prelude::*;
-use std::io::BufReader;
-use std::process::{Command, Stdio};
-use std::str::FromStr;
-use yobmef::chess::{Board, Movement};
-use yobmef::movegen::{gen_moves_once, perft, MoveGen};
-
-#[derive(Debug, PartialEq, Eq)]
-struct PerftResult {
-    mv: Movement,
-    nodes: u64,
+use std::io::{BufReader, Lines};
+use std::process::{self, Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread;
+use yobmef::chess::{Board, STARTING_FEN};
+use yobmef::movegen::{perft_divide, PerftTable};
+
+// Finds perft bugs. Compares our perft against a reference and drills down
+// every subtree where the counts differ, until it gets to positions where
+// the moves themselves are wrong. The reference is either a UCI engine that
+// prints `go perft` divide output (stockfish, or yobmef itself), or an EPD
+// file of known counts, which works offline:
+//
+//   rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400
+//
+// An EPD file can only drill into positions it has counts for, --record
+// writes everything the engine told us to one, for running without it later.
+
+const USAGE: &str = "usage: perft_debug (--engine <path> | --reference <epd>) [--fen <fen>]... \
+                     [--depth <n>] [--chess960] [--record <epd>]";
+
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+struct Options {
+    engine: Option<String>,
+    reference: Option<String>,
+    fens: Vec<String>,
+    depth: Option<u16>,
+    chess960: bool,
+    record: Option<String>,
 }
 
-type PerftResults = Vec<PerftResult>;
-
-fn engine_perft(path: &str, board: &Board, depth: u16) -> Result<PerftResults, Box<dyn Error>> {
-    let mut proc = Command::new(path)
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::piped())
-        .stdin(Stdio::piped())
-        .spawn()?;
-
-    // TODO: Proper error handling with Option
-    let mut stdin = proc.stdin.take().unwrap();
-    let stdout = BufReader::new(proc.stdout.take().unwrap());
-
-    writeln!(stdin, "position fen {}", board.to_fen())?;
-    writeln!(stdin, "go perft {}", depth)?;
-
-    let mut res = Vec::new();
-
-    for line in stdout.lines().skip(1) {
-        let line = line?;
-        match line.trim().split(' ').collect::<Vec<&str>>()[..] {
-            [mv, nodes] => {
-                // eprintln!("mv: {} nodes: {}", mv, nodes);
-                res.push(PerftResult {
-                    mv: Movement::from_notation(&mv[0..4]).unwrap(),
-                    nodes: u64::from_str(nodes)?,
-                });
-            }
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut opts = Options {
+        engine: None,
+        reference: None,
+        fens: Vec::new(),
+        depth: None,
+        chess960: false,
+        record: None,
+    };
 
-            _ => {
-                break;
+    let value = |args: &mut dyn Iterator<Item = String>, flag: &str| {
+        args.next().ok_or(format!("{} needs a value", flag))
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--engine" => opts.engine = Some(value(&mut args, &arg)?),
+            "--reference" => opts.reference = Some(value(&mut args, &arg)?),
+            "--fen" => opts.fens.push(value(&mut args, &arg)?),
+            "--depth" => {
+                opts.depth = Some(
+                    value(&mut args, &arg)?
+                        .parse()
+                        .map_err(|e| format!("--depth: {}", e))?,
+                )
             }
+            "--chess960" => opts.chess960 = true,
+            "--record" => opts.record = Some(value(&mut args, &arg)?),
+            _ => return Err(format!("unexpected argument {}", arg)),
         }
     }
 
-    writeln!(stdin, "quit")?;
+    if opts.engine.is_some() == opts.reference.is_some() {
+        return Err("need one of --engine or --reference".to_string());
+    }
+    if opts.depth == Some(0) {
+        return Err("--depth has to be at least 1".to_string());
+    }
+    if opts.record.is_some() && opts.engine.is_none() {
+        return Err("--record needs --engine".to_string());
+    }
+
+    Ok(opts)
+}
 
-    // TODO: Return Err instead of assert, then panic.
-    let status = proc.wait()?;
-    assert!(status.success(), "exit: {:?}", status);
+// Node counts after each move, by the move in UCI notation
+type Divide = HashMap<String, u64>;
 
-    Ok(res)
+// Where the right answers come from
+trait Reference {
+    // None when we don't know the count
+    fn total(&mut self, board: &Board, depth: u16) -> Result<Option<u64>, Box<dyn Error>>;
+
+    // The divide, and whether it has every move. An EPD file might only
+    // have some of the children, then we can't tell which moves are missing.
+    fn divide(&mut self, board: &Board, depth: u16) -> Result<(Divide, bool), Box<dyn Error>>;
+}
+
+struct Engine {
+    proc: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    record: Option<Epd>,
 }
 
-// Technically I could reuse engine_perft for this, since yobmef
-// prints the same way as stockfish. But that would be very brittle.
-fn yobmef_perft(board: &Board, depth: u16) -> PerftResults {
-    let mut res = Vec::new();
+impl Engine {
+    fn start(path: &str, chess960: bool, record: bool) -> Result<Engine, Box<dyn Error>> {
+        let mut proc = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("can't start {}: {}", path, e))?;
 
-    for mv in MoveGen::new_legal(board) {
-        let nodes = perft(&board.make_move(&mv), depth - 1);
-        res.push(PerftResult { mv, nodes });
+        let stdin = proc.stdin.take().unwrap();
+        let stdout = BufReader::new(proc.stdout.take().unwrap()).lines();
+        let mut engine = Engine {
+            proc,
+            stdin,
+            stdout,
+            record: if record { Some(Epd::default()) } else { None },
+        };
+
+        if chess960 {
+            writeln!(engine.stdin, "setoption name UCI_Chess960 value true")?;
+        }
+        Ok(engine)
     }
 
-    res
+    fn quit(mut self) -> Option<Epd> {
+        let _ = writeln!(self.stdin, "quit");
+        let _ = self.proc.wait();
+        self.record
+    }
+}
+
+impl Reference for Engine {
+    fn total(&mut self, board: &Board, depth: u16) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(Some(self.divide(board, depth)?.0.values().sum()))
+    }
+
+    fn divide(&mut self, board: &Board, depth: u16) -> Result<(Divide, bool), Box<dyn Error>> {
+        writeln!(self.stdin, "position fen {}", board.to_fen())?;
+        writeln!(self.stdin, "go perft {}", depth)?;
+
+        // Lines like `e2e4: 20` up to `Nodes searched: 400`, anything else is noise
+        let mut divide = Divide::new();
+        loop {
+            let line = self
+                .stdout
+                .next()
+                .ok_or("engine quit in the middle of perft")??;
+            if line.starts_with("Nodes searched") {
+                break;
+            }
+            if let Some((mv, nodes)) = line.trim().split_once(": ") {
+                if let Ok(nodes) = nodes.parse() {
+                    divide.insert(mv.to_string(), nodes);
+                }
+            }
+        }
+
+        if let Some(epd) = &mut self.record {
+            epd.insert(board, depth, divide.values().sum());
+
+            // Below depth 1 there's nothing to check, every child is one node
+            if depth > 1 {
+                for (mv, &nodes) in divide.iter() {
+                    if let Some(mv) = board.parse_move(mv).filter(|mv| board.is_legal(mv)) {
+                        epd.insert(&board.make_move(&mv), depth - 1, nodes);
+                    }
+                }
+            }
+        }
+
+        Ok((divide, true))
+    }
 }
 
-// not using get_sorted_moves from engine, because many evals in the opening
-// can be equal, so we might not get the same ordering.
-fn sort(res: &mut PerftResults) {
-    res.sort_by_key(|r| r.mv.to_notation());
+// Known node counts by position and depth
+#[derive(Default)]
+struct Epd {
+    counts: HashMap<String, Vec<(u16, u64)>>,
+    order: Vec<String>, // Positions in the order they were read
+    chess960: bool,     // For writing the moves in a divide
 }
 
-fn join_moves(res: &PerftResults) -> String {
-    res.iter()
-        .map(|r| r.mv.to_notation())
-        .collect::<Vec<String>>()
+// The clocks don't change the counts
+fn position_key(fen: &str) -> String {
+    fen.split_whitespace()
+        .take(4)
+        .collect::<Vec<&str>>()
         .join(" ")
 }
 
-// CURSED, OH SO CURSED
+impl Epd {
+    fn load(path: &str, chess960: bool) -> Result<Epd, Box<dyn Error>> {
+        let mut epd = Epd {
+            chess960,
+            ..Epd::default()
+        };
+
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split(';');
+            let fen = fields.next().unwrap_or("").trim();
+            let board = Board::from_fen(fen)
+                .map_err(|e| format!("line {}: bad fen {}: {}", i + 1, fen, e))?;
+
+            for field in fields {
+                let bad = || format!("line {}: expected `D<depth> <nodes>`, got {}", i + 1, field);
+                let mut words = field.split_whitespace();
+                let depth = words
+                    .next()
+                    .and_then(|d| d.strip_prefix('D'))
+                    .and_then(|d| d.parse().ok())
+                    .ok_or_else(bad)?;
+                if depth == 0 {
+                    return Err(format!("line {}: depth 0 has nothing to check", i + 1).into());
+                }
+                let nodes = words.next().and_then(|n| n.parse().ok()).ok_or_else(bad)?;
+                epd.insert(&board, depth, nodes);
+            }
+        }
+
+        Ok(epd)
+    }
+
+    fn insert(&mut self, board: &Board, depth: u16, nodes: u64) {
+        let key = position_key(&board.to_fen());
+        if !self.counts.contains_key(&key) {
+            self.order.push(key.clone());
+        }
+        let counts = self.counts.entry(key).or_default();
+        if !counts.iter().any(|&(d, _)| d == depth) {
+            counts.push((depth, nodes));
+            counts.sort_unstable();
+        }
+    }
+
+    fn get(&self, board: &Board, depth: u16) -> Option<u64> {
+        let counts = self.counts.get(&position_key(&board.to_fen()))?;
+        counts.iter().find(|&&(d, _)| d == depth).map(|&(_, n)| n)
+    }
+
+    fn save(&self, path: &str, engine: &str) -> Result<(), Box<dyn Error>> {
+        let mut out = format!("# perft counts from {}\n", engine);
+        for key in self.order.iter() {
+            out.push_str(key);
+            for (depth, nodes) in self.counts[key].iter() {
+                out.push_str(&format!(" ;D{} {}", depth, nodes));
+            }
+            out.push('\n');
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+}
+
+impl Reference for Epd {
+    fn total(&mut self, board: &Board, depth: u16) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(self.get(board, depth))
+    }
+
+    // Only the children we have counts for
+    fn divide(&mut self, board: &Board, depth: u16) -> Result<(Divide, bool), Box<dyn Error>> {
+        let mut divide = Divide::new();
+        if depth > 1 {
+            for (mv, _) in perft_divide(board, 1, 1, None) {
+                if let Some(nodes) = self.get(&board.make_move(&mv), depth - 1) {
+                    divide.insert(mv.to_uci(self.chess960), nodes);
+                }
+            }
+        }
+        Ok((divide, false))
+    }
+}
+
+struct Driller<'a> {
+    reference: &'a mut dyn Reference,
+    table: PerftTable,
+    threads: usize,
+    chess960: bool,
+    seen: HashSet<(String, u16)>,
+    failures: usize,
+}
+
+impl Driller<'_> {
+    fn ours(&self, board: &Board, depth: u16) -> Divide {
+        perft_divide(board, depth, self.threads, Some(&self.table))
+            .into_iter()
+            .map(|(mv, n)| (mv.to_uci(self.chess960), n))
+            .collect()
+    }
+
+    // Drill into board where we already know the total is wrong, down to the
+    // smallest positions we can find that are wrong.
+    fn drill(&mut self, board: &Board, depth: u16) -> Result<(), Box<dyn Error>> {
+        if !self.seen.insert((position_key(&board.to_fen()), depth)) {
+            return Ok(());
+        }
+
+        let ours = self.ours(board, depth);
+        let (theirs, complete) = self.reference.divide(board, depth)?;
+
+        let mut missing: Vec<&String> = Vec::new();
+        let mut extra: Vec<&String> = Vec::new();
+        if complete {
+            missing = theirs.keys().filter(|mv| !ours.contains_key(*mv)).collect();
+            extra = ours.keys().filter(|mv| !theirs.contains_key(*mv)).collect();
+            missing.sort();
+            extra.sort();
+        }
+
+        let mut wrong: Vec<&String> = ours
+            .iter()
+            .filter(|(mv, n)| theirs.get(*mv).is_some_and(|want| want != *n))
+            .map(|(mv, _)| mv)
+            .collect();
+        wrong.sort();
 
-// TODO: Change moves to a Vec<Movement> and convert back to string
-// in engine_perft
-fn perft_drill(board: Board, depth: u16) {
-    eprintln!("stockfish perft({})", depth);
-    let mut sf = engine_perft("stockfish", &board, depth).expect("stockfish failed to start");
-    sort(&mut sf);
+        // Moves we agree on but count differently, the problem is further down
+        for mv in wrong.iter() {
+            let child = board.make_move(&board.parse_move(mv).unwrap());
+            self.drill(&child, depth - 1)?;
+        }
 
-    eprintln!("yobmef perft({})", depth);
-    let mut ym = yobmef_perft(&board, depth);
-    sort(&mut ym);
+        // Nothing further down to blame, so this is as small as it gets
+        if !missing.is_empty() || !extra.is_empty() || wrong.is_empty() {
+            self.failures += 1;
+            let want = if complete {
+                Some(theirs.values().sum())
+            } else {
+                self.reference.total(board, depth)?
+            };
+            println!("FAIL {}", board.to_fen());
+            println!(
+                "  depth {}: got {} want {}",
+                depth,
+                ours.values().sum::<u64>(),
+                want.map_or("?".to_string(), |n| n.to_string())
+            );
+            if complete {
+                println!("  missing: {}", join(&missing));
+                println!("  extra:   {}", join(&extra));
+            }
+        }
+        Ok(())
+    }
 
-    // TODO: Instead of a Vec<PerftResult> perhaps have a struct with
-    // a Vec<Movement> and Vec<u64>
-    if depth == 1 {
-        eprintln!("{}\n", board);
+    fn check(&mut self, board: &Board, depth: u16) -> Result<(), Box<dyn Error>> {
+        let want = match self.reference.total(board, depth)? {
+            Some(want) => want,
+            None => return Ok(()),
+        };
+        let got: u64 = self.ours(board, depth).values().sum();
 
-        // TODO: Add fancy diff
-        eprintln!("stockfish legal: {}", join_moves(&sf));
-        eprintln!("yobmef legal:    {}", join_moves(&ym));
+        if got == want {
+            println!("ok   {} depth {}: {}", board.to_fen(), depth, got);
+            Ok(())
+        } else {
+            self.drill(board, depth)
+        }
+    }
+}
 
-        return;
+fn join(moves: &[&String]) -> String {
+    if moves.is_empty() {
+        "<none>".to_string()
+    } else {
+        moves
+            .iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ")
     }
+}
 
-    for (r_sf, r_ym) in sf.iter().zip(ym) {
-        if r_sf.nodes != r_ym.nodes {
-            eprintln!("DIFF {} sf {} yobmef {}", r_sf.mv, r_sf.nodes, r_ym.nodes);
+fn run(opts: &Options) -> Result<usize, Box<dyn Error>> {
+    let fens: Vec<String> = if !opts.fens.is_empty() {
+        opts.fens.clone()
+    } else if opts.reference.is_some() {
+        Vec::new()
+    } else {
+        vec![STARTING_FEN.to_string(), KIWIPETE.to_string()]
+    };
 
-            perft_drill(board.make_move(&r_sf.mv), depth - 1);
+    let mut epd = match &opts.reference {
+        Some(path) => Some(Epd::load(path, opts.chess960)?),
+        None => None,
+    };
+    let mut engine = match &opts.engine {
+        Some(path) => Some(Engine::start(path, opts.chess960, opts.record.is_some())?),
+        None => None,
+    };
 
-            // TODO: Ask "would you like to continue searching for diffs?"
-            // and continue if they say yes.
-            std::process::exit(1);
+    // Everything to check, the given positions or the whole EPD file
+    let mut checks: Vec<(Board, u16)> = Vec::new();
+    for fen in fens.iter() {
+        let board = Board::from_fen(fen).map_err(|e| format!("bad fen {}: {}", fen, e))?;
+        match &epd {
+            Some(epd) if opts.depth.is_none() => {
+                let key = position_key(&board.to_fen());
+                for &(depth, _) in epd.counts.get(&key).into_iter().flatten() {
+                    checks.push((board.clone(), depth));
+                }
+            }
+            _ => checks.push((board, opts.depth.unwrap_or(4))),
+        }
+    }
+    if let Some(epd) = epd.as_ref().filter(|_| fens.is_empty()) {
+        for key in epd.order.iter() {
+            let board = Board::from_fen(key).unwrap();
+            for &(depth, _) in epd.counts[key].iter() {
+                if opts.depth.is_none_or(|max| depth <= max) {
+                    checks.push((board.clone(), depth));
+                }
+            }
         }
     }
+
+    let reference: &mut dyn Reference = match (&mut engine, &mut epd) {
+        (Some(engine), _) => engine,
+        (None, Some(epd)) => epd,
+        (None, None) => unreachable!(),
+    };
+    let mut driller = Driller {
+        reference,
+        table: PerftTable::new(64),
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        chess960: opts.chess960,
+        seen: HashSet::new(),
+        failures: 0,
+    };
+
+    for (board, depth) in checks.iter() {
+        driller.check(board, *depth)?;
+    }
+    let failures = driller.failures;
+
+    if let Some(engine) = engine {
+        if let (Some(recorded), Some(path)) = (engine.quit(), &opts.record) {
+            recorded.save(path, opts.engine.as_ref().unwrap())?;
+        }
+    }
+
+    Ok(failures)
 }
 
 fn main() {
-    gen_moves_once();
-
-    eprintln!("testing startpos");
-    perft_drill(Board::from_start_pos(), 5);
+    let opts = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
 
-    eprintln!("\ntesting the KiwiPete position");
-    perft_drill(
-        Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
-            .unwrap(),
-        5, // only 5 bc its so deeeep
-    );
+    match run(&opts) {
+        Ok(0) => println!("\nno differences"),
+        Ok(failures) => {
+            println!("\n{} failing positions", failures);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
 }
@@ -0,0 +1,256 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::process;
+use std::thread;
+use yobmef::chess::Board;
+use yobmef::eval::{self, EvalParams};
+
+// Texel tuning: https://www.chessprogramming.org/Texel%27s_Tuning_Method
+//
+// Reads quiet positions labelled with the game result, one per line as
+// `<fen> | <result>`, where the result is from white's point of view
+// (1-0, 1/2-1/2, 0-1 or 1.0, 0.5, 0.0). Then tweaks every parameter by one
+// as long as that lowers the error between the game results and the
+// eval squashed through a sigmoid.
+
+const USAGE: &str =
+    "usage: tune <positions> [--params <file>] [--out <file>] [--iterations <n>] [--threads <n>]";
+
+struct Position {
+    board: Board,
+    result: f64, // 1 white won, 0.5 draw, 0 black won
+}
+
+struct Options {
+    positions: String,
+    params: Option<String>,
+    out: String,
+    iterations: usize,
+    threads: usize,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut opts = Options {
+        positions: String::new(),
+        params: None,
+        out: "params.txt".to_string(),
+        iterations: 100,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let value = |args: &mut dyn Iterator<Item = String>, flag: &str| {
+        args.next().ok_or(format!("{} needs a value", flag))
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--params" => opts.params = Some(value(&mut args, &arg)?),
+            "--out" => opts.out = value(&mut args, &arg)?,
+            "--iterations" => {
+                opts.iterations = value(&mut args, &arg)?
+                    .parse()
+                    .map_err(|e| format!("--iterations: {}", e))?
+            }
+            "--threads" => {
+                opts.threads = value(&mut args, &arg)?
+                    .parse()
+                    .map_err(|e| format!("--threads: {}", e))?
+            }
+            _ if opts.positions.is_empty() && !arg.starts_with("--") => opts.positions = arg,
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    if opts.positions.is_empty() {
+        return Err("no positions file".to_string());
+    }
+
+    Ok(opts)
+}
+
+fn parse_result(s: &str) -> Option<f64> {
+    match s.trim().trim_matches(|c| c == '[' || c == ']' || c == '"') {
+        "1-0" | "1" | "1.0" => Some(1.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        "0-1" | "0" | "0.0" => Some(0.0),
+        _ => None,
+    }
+}
+
+fn load_positions(path: &str) -> Result<Vec<Position>, Box<dyn Error>> {
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // The result is always last, so files with extra fields (like a score) work too.
+        let fields: Vec<&str> = line.split('|').collect();
+        let (fen, result) = match fields[..] {
+            [fen, .., result] => (fen, result),
+            _ => return Err(format!("line {}: expected `<fen> | <result>`", i + 1).into()),
+        };
+
+        let board = Board::from_fen(fen.trim())
+            .map_err(|e| format!("line {}: bad fen {}: {}", i + 1, fen.trim(), e))?;
+        let result =
+            parse_result(result).ok_or_else(|| format!("line {}: bad result {}", i + 1, result))?;
+
+        // The static eval is meaningless here, these shouldn't be in the set anyway
+        if board.in_check() {
+            skipped += 1;
+            continue;
+        }
+
+        positions.push(Position { board, result });
+    }
+
+    if skipped > 0 {
+        eprintln!("skipped {} positions in check", skipped);
+    }
+
+    Ok(positions)
+}
+
+#[inline]
+fn sigmoid(k: f64, score: f64) -> f64 {
+    1.0 / (1.0 + f64::powf(10.0, -k * score / 400.0))
+}
+
+// Mean squared error over all positions, split across threads.
+fn error(positions: &[Position], params: &EvalParams, k: f64, threads: usize) -> f64 {
+    let chunk_size = (positions.len() / threads).max(1);
+
+    let total: f64 = thread::scope(|s| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|p| {
+                            let score = eval::evaluate(params, &p.board) as f64;
+                            (p.result - sigmoid(k, score)).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+
+    total / positions.len() as f64
+}
+
+// The scaling constant that best fits our current eval to the results,
+// found by narrowing down around the best K one digit at a time.
+fn find_k(positions: &[Position], params: &EvalParams, threads: usize) -> f64 {
+    let mut best = (1.0, error(positions, params, 1.0, threads));
+    let mut step = 1.0;
+
+    for _ in 0..4 {
+        let start = best.0;
+        for i in -10..=10 {
+            let k = start + i as f64 * step;
+            if k <= 0.0 {
+                continue;
+            }
+
+            let e = error(positions, params, k, threads);
+            if e < best.1 {
+                best = (k, e);
+            }
+        }
+        step /= 10.0;
+    }
+
+    best.0
+}
+
+fn tune(positions: &[Position], mut params: EvalParams, opts: &Options) -> EvalParams {
+    let k = find_k(positions, &params, opts.threads);
+    let mut values = params.to_vec();
+    let mut best_error = error(positions, &params, k, opts.threads);
+    eprintln!("K = {:.3}, starting error {:.6}", k, best_error);
+
+    // Values the eval never reads can't lower the error, don't spend time on them
+    let tunable: Vec<usize> = EvalParams::tunable()
+        .iter()
+        .enumerate()
+        .filter(|(_, &t)| t)
+        .map(|(i, _)| i)
+        .collect();
+    eprintln!("tuning {} of {} values", tunable.len(), values.len());
+
+    for iteration in 1..=opts.iterations {
+        let mut improved = false;
+
+        for &i in tunable.iter() {
+            for &delta in [1, -1].iter() {
+                values[i] += delta;
+                params.set_from_slice(&values);
+
+                let e = error(positions, &params, k, opts.threads);
+                if e < best_error {
+                    best_error = e;
+                    improved = true;
+                    break;
+                }
+
+                values[i] -= delta;
+            }
+        }
+
+        params.set_from_slice(&values);
+        eprintln!("iteration {}: error {:.6}", iteration, best_error);
+
+        // Save as we go, tuning takes a while
+        if let Err(e) = params.save(&opts.out) {
+            eprintln!("failed to save {}: {}", opts.out, e);
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    params
+}
+
+fn main() {
+    let opts = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(1);
+    });
+
+    let params = match &opts.params {
+        Some(path) => EvalParams::load(path).unwrap_or_else(|e| {
+            eprintln!("failed to load {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => EvalParams::default(),
+    };
+
+    let positions = load_positions(&opts.positions).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    if positions.is_empty() {
+        eprintln!("no positions to tune on");
+        process::exit(1);
+    }
+    eprintln!("loaded {} positions", positions.len());
+
+    let params = tune(&positions, params, &opts);
+    if let Err(e) = params.save(&opts.out) {
+        eprintln!("failed to save {}: {}", opts.out, e);
+        process::exit(1);
+    }
+    eprintln!("wrote {}", opts.out);
+}
@@ -61,7 +61,7 @@ impl BitBoard {
 
         self.0 = ((self.0 >> 8) & K1) | ((self.0 & K1) << 8);
         self.0 = ((self.0 >> 16) & K2) | ((self.0 & K2) << 16);
-        self.0 = (self.0 >> 32) | (self.0 << 32);
+        self.0 = self.0.rotate_left(32);
     }
 
     pub fn flip_vertical_if(&self, condition: bool) -> BitBoard {
@@ -92,6 +92,12 @@ impl BitBoard {
         BitBoard(1 << sq.0)
     }
 
+    // Every square on the file, 0 is the a file
+    #[inline]
+    pub fn file_mask(file: u8) -> BitBoard {
+        BitBoard(0x0101010101010101 << file)
+    }
+
     // Convert this bitboard to a Square.
     // If the bitboard has multiple bits flipped,
     // This function must still return a valid square.
@@ -247,6 +253,14 @@ mod tests {
         assert!(b3.get(Square::new(0, 3)));
     }
 
+    #[test]
+    fn test_file_mask() {
+        let c = BitBoard::file_mask(2);
+        assert_eq!(c.count_ones(), 8);
+        assert!((0..8).all(|rank| c.get(Square::new(rank, 2))));
+        assert_eq!(BitBoard::file_mask(7), BitBoard(0x8080808080808080));
+    }
+
     #[test]
     fn test_bitboard_population() {
         let mut b = BitBoard::empty();
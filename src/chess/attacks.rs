@@ -0,0 +1,162 @@
+use crate::bitboard::BitBoard;
+use crate::chess::*;
+use crate::movegen;
+
+// Attack queries, worked out when asked for instead of kept up to date
+// after every move.
+impl Board {
+    // Pieces of both colors attacking sq, with sliders blocked by occupancy.
+    // Pass a different occupancy to see through pieces, like the king when
+    // checking where it can go.
+    pub fn attackers_to(&self, sq: Square, occupancy: BitBoard) -> BitBoard {
+        let white = *self.color_combined(Color::White);
+        let black = *self.color_combined(Color::Black);
+        let pawns = *self.pieces(Piece::Pawn);
+        let queens = *self.pieces(Piece::Queen);
+        let rook_likes = *self.pieces(Piece::Rook) | queens;
+        let bishop_likes = *self.pieces(Piece::Bishop) | queens;
+
+        // A white pawn attacks sq if a black pawn on sq would attack it
+        (movegen::pawn_attacks(sq, Color::Black) & pawns & white)
+            | (movegen::pawn_attacks(sq, Color::White) & pawns & black)
+            | (movegen::knight_attacks(sq) & *self.pieces(Piece::Knight))
+            | (movegen::king_attacks(sq) & *self.pieces(Piece::King))
+            | (movegen::rook_attacks(sq, occupancy) & rook_likes)
+            | (movegen::bishop_attacks(sq, occupancy) & bishop_likes)
+    }
+
+    pub fn is_attacked_by(&self, sq: Square, color: Color) -> bool {
+        self.attackers_to(sq, self.combined()) & *self.color_combined(color) != BitBoard::empty()
+    }
+
+    // Every square color attacks
+    pub fn attacked(&self, color: Color) -> BitBoard {
+        movegen::get_attacked_squares(self, color)
+    }
+
+    // Pieces giving check to the side to move. Nothing without a king (antichess).
+    pub fn checkers(&self) -> BitBoard {
+        let us = self.side_to_move;
+        let king = *self.pieces(Piece::King) & *self.color_combined(us);
+        if king.count_ones() != 1 || !self.variant.has_royal_king() {
+            return BitBoard::empty();
+        }
+
+        self.attackers_to(self.king(us), self.combined()) & *self.color_combined(us.other())
+    }
+
+    // Pieces of color that can't move off the line between their king and
+    // an enemy slider.
+    pub fn pinned(&self, color: Color) -> BitBoard {
+        self.blockers(color, color).0
+    }
+
+    // Enemy sliders pinning a piece of color to its king
+    pub fn pinners(&self, color: Color) -> BitBoard {
+        self.blockers(color, color).1
+    }
+
+    // Pieces of color in the way of one of its own sliders and the enemy king,
+    // moving them off the line is a discovered check.
+    pub fn discoverers(&self, color: Color) -> BitBoard {
+        self.blockers(color.other(), color).0
+    }
+
+    // Lone pieces of blocker_color between king_color's king and the other
+    // side's sliders, and the sliders behind them.
+    fn blockers(&self, king_color: Color, blocker_color: Color) -> (BitBoard, BitBoard) {
+        let mut blockers = BitBoard::empty();
+        let mut snipers_behind = BitBoard::empty();
+
+        let king = *self.pieces(Piece::King) & *self.color_combined(king_color);
+        if king.count_ones() != 1 {
+            return (blockers, snipers_behind);
+        }
+        let king = self.king(king_color);
+
+        // Sliders that would hit the king on an empty board
+        let queens = *self.pieces(Piece::Queen);
+        let snipers = ((movegen::rook_attacks(king, BitBoard::empty())
+            & (*self.pieces(Piece::Rook) | queens))
+            | (movegen::bishop_attacks(king, BitBoard::empty())
+                & (*self.pieces(Piece::Bishop) | queens)))
+            & *self.color_combined(king_color.other());
+
+        let occupancy = self.combined();
+        let ours = *self.color_combined(blocker_color);
+        for sniper in snipers {
+            let between = movegen::between(king, sniper) & occupancy;
+            if between.count_ones() == 1 && between & ours != BitBoard::empty() {
+                blockers |= between;
+                snipers_behind |= BitBoard::from_square(sniper);
+            }
+        }
+
+        (blockers, snipers_behind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn squares(s: &str) -> BitBoard {
+        s.split_whitespace()
+            .map(|sq| BitBoard::from_square(Square::from_notation(sq).unwrap()))
+            .fold(BitBoard::empty(), |a, b| a | b)
+    }
+
+    fn sq(s: &str) -> Square {
+        Square::from_notation(s).unwrap()
+    }
+
+    #[test]
+    fn test_attackers_to() {
+        let board = Board::from_fen("4k3/8/2n5/3p4/4P3/8/1B6/R3K3 w - - 0 1").unwrap();
+        let occupancy = board.combined();
+
+        assert_eq!(board.attackers_to(sq("d5"), occupancy), squares("e4"));
+        assert_eq!(board.attackers_to(sq("e4"), occupancy), squares("d5"));
+        assert_eq!(board.attackers_to(sq("e5"), occupancy), squares("b2 c6"));
+        assert_eq!(board.attackers_to(sq("d1"), occupancy), squares("a1 e1"));
+
+        // Seeing through the king, like we need when it moves along the rook's rank
+        assert_eq!(board.attackers_to(sq("f1"), occupancy), squares("e1"));
+        let occupancy = occupancy & !squares("e1");
+        assert_eq!(board.attackers_to(sq("f1"), occupancy), squares("a1 e1"));
+
+        assert!(board.is_attacked_by(sq("d5"), Color::White));
+        assert!(!board.is_attacked_by(sq("e4"), Color::White));
+    }
+
+    #[test]
+    fn test_checkers() {
+        let board = Board::from_start_pos();
+        assert_eq!(board.checkers(), BitBoard::empty());
+
+        // Double check, knight and rook
+        let board = Board::from_fen("4k3/8/3N4/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+        assert_eq!(board.checkers(), squares("d6 e1"));
+        assert!(board.in_check());
+
+        // A pawn attacking the back rank
+        let board = Board::from_fen("8/8/8/8/8/8/3p4/4K2k w - - 0 1").unwrap();
+        assert_eq!(board.checkers(), squares("d2"));
+    }
+
+    #[test]
+    fn test_pinned() {
+        // Two pieces in the way of the queen on h1 means no pin
+        let board = Board::from_fen("4r2k/8/8/8/1b6/8/3NP3/4KBNq w - - 0 1").unwrap();
+        assert_eq!(board.pinned(Color::White), squares("d2 e2"));
+        assert_eq!(board.pinners(Color::White), squares("b4 e8"));
+        assert_eq!(board.pinned(Color::Black), BitBoard::empty());
+
+        // Our piece in front of their king isn't pinned, it's a discovered check
+        let board = Board::from_fen("4k3/8/8/4N3/8/8/8/4RK2 w - - 0 1").unwrap();
+        assert_eq!(board.pinned(Color::White), BitBoard::empty());
+        assert_eq!(board.pinned(Color::Black), BitBoard::empty());
+        assert_eq!(board.discoverers(Color::White), squares("e5"));
+        assert_eq!(board.discoverers(Color::Black), BitBoard::empty());
+    }
+}
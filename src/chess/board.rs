@@ -1,18 +1,80 @@
 use crate::bitboard::BitBoard;
 use crate::chess::*;
 use crate::movegen;
+use std::error::Error;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Board {
     pub pieces: [BitBoard; NUM_PIECES],
     pub color_combined: [BitBoard; NUM_COLORS],
+    pub mailbox: [Option<(Piece, Color)>; 64], // Same as the bitboards, by square
     pub en_passant: Option<Square>,
     pub side_to_move: Color,
     pub castling: u8, // 4 bits needed, from rtl: white kingside, white queenside, black kingside, black queenside
-    pub attacked: [BitBoard; NUM_COLORS], // Colors white attacks, Colors black attacks.
+    pub rook_files: [u8; 4], // Where the castling rooks start, by CastlingSide. Not a/h in Chess960.
+
+    // Variant state, all zero in standard chess. See variant.rs
+    pub variant: Variant,
+    pub checks: [u8; NUM_COLORS], // Checks given, three-check
+    pub pockets: [[u8; NUM_PIECES]; NUM_COLORS], // Pieces to drop, crazyhouse
+    pub promoted: BitBoard,       // Promoted pieces go back to the pocket as pawns, crazyhouse
+}
+
+// The mailbox follows from the bitboards, no need to hash it too
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pieces.hash(state);
+        self.color_combined.hash(state);
+        self.en_passant.hash(state);
+        self.side_to_move.hash(state);
+        self.castling.hash(state);
+        self.rook_files.hash(state);
+        self.variant.hash(state);
+        self.checks.hash(state);
+        self.pockets.hash(state);
+        self.promoted.hash(state);
+    }
 }
 
+// Positions that parse fine but can't come up in a game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    WrongKingCount { color: Color, count: u8 },
+    PawnOnBackRank(Square),
+    OpponentInCheck,
+    InvalidEnPassant(Square),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionError::WrongKingCount { color, count } => {
+                write!(f, "{:?} has {} kings", color, count)
+            }
+            PositionError::PawnOnBackRank(sq) => write!(f, "pawn on the back rank at {}", sq),
+            PositionError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            PositionError::InvalidEnPassant(sq) => {
+                write!(f, "en passant on {} but no pawn just moved there", sq)
+            }
+        }
+    }
+}
+
+impl Error for PositionError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IllegalMove(pub Movement);
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "illegal move {}", self.0)
+    }
+}
+
+impl Error for IllegalMove {}
+
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "side to move: {:?}", self.side_to_move)?;
@@ -41,32 +103,91 @@ impl fmt::Display for Board {
 }
 
 impl Board {
-    // Replace old piece with new piece, return old piece,
+    // Replace old piece with a piece of the side to move, return old piece,
     // if the board is invalid, get ready for some fun debugging
     pub fn replace_mut(&mut self, piece: Piece, square: Square) -> Option<Piece> {
-        let old_piece = self.piece_on(square);
-        if let Some(old_piece) = old_piece {
-            self.pieces[old_piece as usize].flip_mut(square);
-            self.color_combined[self.side_to_move.other() as usize].flip_mut(square);
-        }
+        let old_piece = self.remove_mut(square);
+        self.put_mut(piece, self.side_to_move, square);
+        old_piece
+    }
 
+    // Put a piece on an empty square, everything that adds pieces goes through here
+    // so the mailbox stays in sync with the bitboards.
+    pub(super) fn put_mut(&mut self, piece: Piece, color: Color, square: Square) {
+        debug_assert!(
+            self.mailbox[square.0 as usize].is_none(),
+            "{} taken",
+            square
+        );
         self.pieces[piece as usize].flip_mut(square);
-
-        old_piece
+        self.color_combined[color as usize].flip_mut(square);
+        self.mailbox[square.0 as usize] = Some((piece, color));
     }
 
     pub fn in_check(&self) -> bool {
-        let attacked = self.attacked(self.side_to_move.other());
-
-        let our_pieces = self.color_combined(self.side_to_move);
-        let our_king = *self.pieces(Piece::King) & our_pieces;
-        (our_king & attacked).0 != 0
+        self.checkers() != BitBoard::empty()
     }
 
     pub fn is_capture(&self, mv: &Movement) -> bool {
         let to_square_bb = BitBoard::from_square(mv.to_square);
         let enemy_pieces = self.color_combined(self.side_to_move.other());
-        to_square_bb & enemy_pieces != BitBoard::empty()
+        to_square_bb & enemy_pieces != BitBoard::empty() || self.is_en_passant(mv)
+    }
+
+    fn is_en_passant(&self, mv: &Movement) -> bool {
+        self.en_passant == Some(mv.to_square)
+            && self.pieces(Piece::Pawn).get(mv.from_square)
+            && mv.hdelta() != 0
+    }
+
+    // Work out the kind of a (pseudo legal) move, for moves that didn't come
+    // from the move generator, like the ones in a uci position command.
+    // Castling is the king capturing its own rook, see resolve_move.
+    pub fn move_kind(&self, mv: &Movement) -> MoveKind {
+        let piece = self.piece_on(mv.from_square);
+
+        if mv.is_drop() {
+            mv.kind
+        } else if piece == Some(Piece::King)
+            && self.color_on(mv.to_square) == Some(self.side_to_move)
+        {
+            self.castling_side_for_rook(mv.to_square)
+                .map_or(MoveKind::Quiet, MoveKind::Castle)
+        } else if let Some(captured) = self.piece_on(mv.to_square) {
+            MoveKind::Capture(captured)
+        } else if self.is_en_passant(mv) {
+            MoveKind::EnPassant
+        } else if piece == Some(Piece::Pawn) && i8::abs(mv.vdelta()) == 2 {
+            MoveKind::DoublePush
+        } else {
+            MoveKind::Quiet
+        }
+    }
+
+    // Moves from outside use the standard e1g1 for castling, or e1h1 (king takes
+    // rook) in Chess960. Either way we want king takes rook, with the kind set.
+    pub fn resolve_move(&self, mv: &Movement) -> Movement {
+        let mut mv = mv.clone();
+
+        // A king never moves two files, unless it's castling
+        if self.pieces(Piece::King).get(mv.from_square) && i8::abs(mv.hdelta()) >= 2 {
+            let castling = CastlingSide::of_color(self.side_to_move)
+                .iter()
+                .copied()
+                .find(|side| {
+                    self.can_castle_unchecked(*side) && side.king_destination() == mv.to_square
+                });
+            if let Some(side) = castling {
+                mv.to_square = self.castling_rook(side);
+            }
+        }
+
+        let kind = self.move_kind(&mv);
+        mv.with_kind(kind)
+    }
+
+    pub fn parse_move(&self, lan: &str) -> Option<Movement> {
+        Movement::from_notation(lan).map(|mv| self.resolve_move(&mv))
     }
 
     // TODO: Needed?
@@ -76,15 +197,42 @@ impl Board {
         board
     }
 
-    // Same as replace_mut but removes the piece at the square
-    pub fn remove_mut(&mut self, square: Square) -> Option<Piece> {
-        let old_piece = self.piece_on(square);
-        if let Some(old_piece) = old_piece {
-            self.pieces[old_piece as usize].flip_mut(square);
-            self.color_combined[self.side_to_move.other() as usize].flip_mut(square);
+    // Flip the board vertically and swap the colors, so white's position
+    // becomes black's and vice versa. Useful for testing symmetry.
+    pub fn mirror(&self) -> Self {
+        let mut board = Board::empty();
+
+        for sq in self.combined() {
+            let (piece, color) = self.mailbox[sq.0 as usize].unwrap();
+            board.put_mut(piece, color.other(), sq.flip_vertical());
         }
 
-        old_piece
+        board.en_passant = self.en_passant.map(|sq| sq.flip_vertical());
+        board.side_to_move = self.side_to_move.other();
+
+        board.variant = self.variant;
+        board.checks = [self.checks[1], self.checks[0]];
+        board.pockets = [self.pockets[1], self.pockets[0]];
+        board.promoted = self.promoted.flip_vertical();
+
+        // White's castling bits are the low two, black's the high two
+        board.castling = ((self.castling & 0b0011) << 2) | ((self.castling >> 2) & 0b0011);
+        board.rook_files = [
+            self.rook_files[2],
+            self.rook_files[3],
+            self.rook_files[0],
+            self.rook_files[1],
+        ];
+
+        board
+    }
+
+    // Same as replace_mut but removes the piece at the square
+    pub fn remove_mut(&mut self, square: Square) -> Option<Piece> {
+        let (old_piece, color) = self.mailbox[square.0 as usize].take()?;
+        self.pieces[old_piece as usize].flip_mut(square);
+        self.color_combined[color as usize].flip_mut(square);
+        Some(old_piece)
     }
 
     pub fn assert_valid(&self) {
@@ -93,6 +241,14 @@ impl Board {
         for sq in 0..64 {
             let sq = Square(sq);
 
+            // The mailbox has to agree with the bitboards
+            let expected = self.pieces_on_bitboards(sq);
+            assert_eq!(
+                self.mailbox[sq.0 as usize], expected,
+                "mailbox wrong on {}",
+                sq
+            );
+
             if bitboard.get(sq) {
                 // Multiple pieces on the same square
                 let num_on_square: u8 = (0..NUM_PIECES).map(|p| self.pieces[p].get(sq) as u8).sum();
@@ -127,34 +283,87 @@ impl Board {
         assert_eq!(num_black_kings, 1, "{} black kings", num_black_kings);
     }
 
+    // Like assert_valid, but for positions from the outside world, which
+    // can't have overlapping pieces but can be nonsense in other ways.
+    pub fn validate(&self) -> Result<(), PositionError> {
+        // Antichess kings can be taken or promoted to like anything else
+        let royal = self.variant.has_royal_king();
+
+        for &color in [Color::White, Color::Black].iter() {
+            let count = (*self.pieces(Piece::King) & *self.color_combined(color)).count_ones();
+            if royal && count != 1 {
+                return Err(PositionError::WrongKingCount { color, count });
+            }
+        }
+
+        let back_ranks = BitBoard(0xff000000000000ff);
+        if let Some(sq) = (*self.pieces(Piece::Pawn) & back_ranks).next() {
+            return Err(PositionError::PawnOnBackRank(sq));
+        }
+
+        if royal {
+            let their_king = self.king(self.side_to_move.other());
+            if self.is_attacked_by(their_king, self.side_to_move) {
+                return Err(PositionError::OpponentInCheck);
+            }
+        }
+
+        if let Some(ep) = self.en_passant {
+            // The pawn that moved two squares is in front of the en passant square,
+            // and it came from behind it.
+            let (pushed, from) = match self.side_to_move {
+                Color::White => (ep.down(1), ep.up(1)),
+                Color::Black => (ep.up(1), ep.down(1)),
+            };
+            let their_pawns =
+                *self.pieces(Piece::Pawn) & *self.color_combined(self.side_to_move.other());
+            let pawn_there = pushed.is_some_and(|sq| their_pawns.get(sq));
+            let combined = self.combined();
+            let empty_behind = from.is_some_and(|sq| !combined.get(sq)) && !combined.get(ep);
+
+            if !pawn_there || !empty_behind {
+                return Err(PositionError::InvalidEnPassant(ep));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn empty() -> Board {
         Board {
             pieces: [BitBoard(0); NUM_PIECES],
             color_combined: [BitBoard(0); NUM_COLORS],
+            mailbox: [None; 64],
             en_passant: None,
             castling: 0b1111,
+            rook_files: [7, 0, 7, 0],
             side_to_move: Color::White,
-            attacked: [BitBoard(0); NUM_COLORS],
+            variant: Variant::Standard,
+            checks: [0; NUM_COLORS],
+            pockets: [[0; NUM_PIECES]; NUM_COLORS],
+            promoted: BitBoard(0),
         }
     }
 
+    #[inline]
     pub fn piece_on(&self, square: Square) -> Option<Piece> {
-        for piece in 0..NUM_PIECES {
-            if self.pieces[piece].get(square) {
-                return Some(Piece::from_usize(piece).unwrap());
-            }
-        }
-        None
+        self.mailbox[square.0 as usize].map(|(piece, _)| piece)
     }
 
+    #[inline]
     pub fn color_on(&self, square: Square) -> Option<Color> {
-        if self.color_combined(Color::White).get(square) {
-            Some(Color::White)
-        } else if self.color_combined(Color::Black).get(square) {
-            Some(Color::Black)
+        self.mailbox[square.0 as usize].map(|(_, color)| color)
+    }
+
+    // The slow way, for checking the mailbox
+    fn pieces_on_bitboards(&self, square: Square) -> Option<(Piece, Color)> {
+        let piece = (0..NUM_PIECES).find(|&p| self.pieces[p].get(square))?;
+        let color = if self.color_combined(Color::White).get(square) {
+            Color::White
         } else {
-            None
-        }
+            Color::Black
+        };
+        Some((Piece::from_usize(piece).unwrap(), color))
     }
 
     pub fn pieces(&self, piece: Piece) -> &BitBoard {
@@ -169,73 +378,27 @@ impl Board {
         *self.color_combined(Color::White) | *self.color_combined(Color::Black)
     }
 
-    pub fn attacked(&self, color: Color) -> BitBoard {
-        self.attacked[color as usize]
+    // X-FEN, the same as a normal fen unless it's a Chess960 position
+    // where KQkq would be ambiguous.
+    pub fn to_fen(&self) -> String {
+        self.fen(false)
     }
 
-    pub fn update_attackers(&mut self) {
-        self.attacked[Color::White as usize] = movegen::get_attacked_squares(self, Color::White);
-        self.attacked[Color::Black as usize] = movegen::get_attacked_squares(self, Color::Black);
+    // Shredder-FEN, castling rights are always rook files (HAha)
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen(true)
     }
 
-    pub fn from_fen(s: &str) -> Option<Board> {
-        // In release mode, checking a sync.Once all the time is cringe
-        if cfg!(test) {
-            movegen::gen_moves_once();
-        }
-
-        let mut board = Board::empty();
-
-        let mut fen_split = s.split(' ');
-        let board_split = fen_split.next()?.split('/');
-
-        let mut rank_index = 8;
-        for rank in board_split {
-            rank_index -= 1;
-            let mut file_index: u8 = 0;
-
-            for piece_char in rank.chars() {
-                if piece_char.is_numeric() {
-                    file_index += piece_char.to_digit(10)? as u8;
-                } else {
-                    let piece = Piece::from_char(piece_char.to_ascii_lowercase())?;
-                    let color = if piece_char.is_uppercase() {
-                        Color::White
-                    } else {
-                        Color::Black
-                    };
-                    let sq = Square::new(rank_index, file_index);
-
-                    board.pieces[piece as usize].flip_mut(sq);
-                    board.color_combined[color as usize].flip_mut(sq);
-                    file_index += 1;
-                }
-            }
-        }
-
-        board.side_to_move = if fen_split.next()? == "w" {
-            Color::White
-        } else {
-            Color::Black
-        };
-
-        let castling_string = fen_split.next()?;
-        board.set_castling_mut(CastlingSide::WhiteKingside, castling_string.contains('K'));
-        board.set_castling_mut(CastlingSide::WhiteQueenside, castling_string.contains('Q'));
-        board.set_castling_mut(CastlingSide::BlackKingside, castling_string.contains('k'));
-        board.set_castling_mut(CastlingSide::BlackQueenside, castling_string.contains('q'));
-
-        let en_passant = fen_split.next()?;
-        if en_passant.len() == 2 {
-            board.en_passant = Square::from_notation(&en_passant[0..2]);
-        }
-
-        board.update_attackers();
-        Some(board)
+    // Is the rook for this side the one furthest from the king? Then K or Q is enough.
+    fn is_outer_rook(&self, side: CastlingSide) -> bool {
+        let rook = self.castling_rook(side);
+        let edge = Square::new(side.back_rank(), if side.is_kingside() { 7 } else { 0 });
+        let rooks = *self.pieces(Piece::Rook) & *self.color_combined(side.color());
+        (rank_span(rook, edge) & rooks).count_ones() == 1
     }
 
     // TODO: Clean up, this is awful code.
-    pub fn to_fen(&self) -> String {
+    fn fen(&self, shredder: bool) -> String {
         let mut buf = String::new();
 
         for rank in 0..8 {
@@ -251,6 +414,9 @@ impl Board {
                         since_last_piece = 0;
                     }
                     buf.push(piece.as_char_color(self.color_on(sq).unwrap()));
+                    if self.variant == Variant::Crazyhouse && self.promoted.get(sq) {
+                        buf.push('~');
+                    }
                 } else {
                     since_last_piece += 1;
                 }
@@ -262,23 +428,43 @@ impl Board {
             buf.push('/');
         }
         buf.pop();
+
+        if self.variant == Variant::Crazyhouse {
+            buf.push('[');
+            for &color in [Color::White, Color::Black].iter() {
+                for &piece in POCKET_PIECES.iter() {
+                    for _ in 0..self.pocket(color, piece) {
+                        buf.push(piece.as_char_color(color));
+                    }
+                }
+            }
+            buf.push(']');
+        }
+
         buf.push(' ');
         buf.push(self.side_to_move.as_char());
         buf.push(' ');
 
-        // tfw tenary is too verbose for you
-        macro_rules! kk {
-            ($side:ident, $character:expr) => {
-                if self.can_castle_unchecked(CastlingSide::$side) {
-                    buf.push($character);
-                }
-            };
-        }
         if self.castling != 0 {
-            kk!(WhiteKingside, 'K');
-            kk!(WhiteQueenside, 'Q');
-            kk!(BlackKingside, 'k');
-            kk!(BlackQueenside, 'q');
+            for &side in CastlingSide::ALL.iter() {
+                if !self.can_castle_unchecked(side) {
+                    continue;
+                }
+
+                let c = if !shredder && self.is_outer_rook(side) {
+                    if side.is_kingside() {
+                        'k'
+                    } else {
+                        'q'
+                    }
+                } else {
+                    (b'a' + self.rook_files[side as usize]) as char
+                };
+                buf.push(match side.color() {
+                    Color::White => c.to_ascii_uppercase(),
+                    Color::Black => c,
+                });
+            }
         } else {
             buf.push('-');
         }
@@ -292,6 +478,15 @@ impl Board {
                 .unwrap_or("-".to_string()),
         );
 
+        // Checks left, like lichess does it
+        if self.variant == Variant::ThreeCheck {
+            buf.push_str(&format!(
+                " {}+{}",
+                3u8.saturating_sub(self.checks(Color::White)),
+                3u8.saturating_sub(self.checks(Color::Black))
+            ));
+        }
+
         // TODO: Halfmove clock, Fullmove number
         buf.push_str(" 0 1");
 
@@ -314,9 +509,24 @@ impl Board {
             self.castling |= 1 << side_bit;
         } else {
             self.castling &= !(1 << side_bit);
+            // So boards with the same rights compare equal
+            self.rook_files[side as usize] = side.default_rook_file();
         }
     }
 
+    // Where the rook for this side starts, only meaningful if we can castle there
+    #[inline]
+    pub fn castling_rook(&self, side: CastlingSide) -> Square {
+        Square::new(side.back_rank(), self.rook_files[side as usize])
+    }
+
+    pub fn castling_side_for_rook(&self, sq: Square) -> Option<CastlingSide> {
+        CastlingSide::ALL
+            .iter()
+            .copied()
+            .find(|side| self.can_castle_unchecked(*side) && self.castling_rook(*side) == sq)
+    }
+
     // Checks for castling privileges but doesn't check square occupancy
     pub fn can_castle_unchecked(&self, side: CastlingSide) -> bool {
         let side_bit = side as u8;
@@ -329,9 +539,45 @@ impl Board {
         board
     }
 
-    // This function WILL break if passed invalid moves
+    // The generated move matching mv, so it comes with the right kind.
+    fn find_legal(&self, mv: &Movement) -> Option<Movement> {
+        let mv = self.resolve_move(mv);
+        movegen::MoveGen::new_legal(self).find(|legal| *legal == mv)
+    }
+
+    pub fn is_legal(&self, mv: &Movement) -> bool {
+        self.find_legal(mv).is_some()
+    }
+
+    // For moves from the outside world, leaves the board alone if mv is illegal.
+    pub fn try_make_move(&mut self, mv: &Movement) -> Result<(), IllegalMove> {
+        let legal = self.find_legal(mv).ok_or_else(|| IllegalMove(mv.clone()))?;
+        self.make_move_mut(&legal);
+        Ok(())
+    }
+
+    // Whether mv is what resolve_move would make of it, the form
+    // make_move_mut expects.
+    fn is_resolved(&self, mv: &Movement) -> bool {
+        let resolved = self.resolve_move(mv);
+        resolved.to_square == mv.to_square && resolved.kind == mv.kind
+    }
+
+    // This function WILL break if passed invalid moves,
+    // and moves need their kind set (see resolve_move). Use try_make_move
+    // for moves you don't trust.
     pub fn make_move_mut(&mut self, movement: &Movement) {
-        // TODO: Clean up using math instead of tenary conditionals.
+        debug_assert!(
+            self.is_resolved(movement),
+            "unresolved move {} {:?}, see resolve_move",
+            movement,
+            movement.kind
+        );
+
+        if let MoveKind::Drop(piece) = movement.kind {
+            self.drop_mut(piece, movement.to_square);
+            return;
+        }
 
         let color = self
             .color_on(movement.from_square)
@@ -342,56 +588,45 @@ impl Board {
             .piece_on(movement.from_square)
             .expect("no piece on square");
 
-        // Piece specific logic
-        match piece {
-            Piece::King => {
-                let castling = CastlingSide::from_movement(movement);
-                if let Some(castling) = castling {
-                    debug_assert!(
-                        self.can_castle_unchecked(castling),
-                        "tried to castle ({:?}) but cannot castle",
-                        castling,
-                    );
-                    self.make_move_mut(&castling.get_rook_movement());
-
-                    // self.make_move_mut changed the side to move, change it back!
-                    // TODO: We should really inline this, or inline a call to a helper.
-                    // Recursion here is not ideal for maintainability.
-                    self.side_to_move = self.side_to_move.other();
-                }
-
-                // No matter what king move, we can no longer castle.
-                // TODO: Optimize using bitwise operations
-                CastlingSide::of_color(color)
-                    .iter()
-                    .for_each(|side| self.set_castling_mut(*side, false));
+        // Moving a rook from its corner, or capturing it, loses that castling right
+        for &sq in [movement.from_square, movement.to_square].iter() {
+            if let Some(side) = self.castling_side_for_rook(sq) {
+                self.set_castling_mut(side, false);
             }
+        }
 
-            Piece::Rook => {
-                // You can no longer castle on this side after moving your rook.
-                if let Some(side) = CastlingSide::from_rook_square(movement.from_square) {
-                    self.set_castling_mut(side, false)
-                }
+        if self.variant == Variant::Crazyhouse {
+            self.update_pockets_mut(movement);
+        }
+
+        match movement.kind {
+            MoveKind::Castle(castling) => {
+                self.castle_mut(castling, movement);
+                return;
             }
 
-            Piece::Pawn => {
-                if self.en_passant == Some(movement.to_square) {
-                    // Remove the captured pawn
-                    self.remove_mut(if self.side_to_move == Color::White {
-                        movement.to_square.down(1).unwrap()
-                    } else {
-                        movement.to_square.up(1).unwrap()
-                    });
-                }
+            MoveKind::EnPassant => {
+                // Remove the captured pawn
+                self.remove_mut(if self.side_to_move == Color::White {
+                    movement.to_square.down(1).unwrap()
+                } else {
+                    movement.to_square.up(1).unwrap()
+                });
             }
 
             _ => {}
         }
 
-        // Store en passant passing square
-        let is_double_move = piece == Piece::Pawn && i8::abs(movement.vdelta()) == 2;
+        if piece == Piece::King {
+            // No matter what king move, we can no longer castle.
+            // TODO: Optimize using bitwise operations
+            CastlingSide::of_color(color)
+                .iter()
+                .for_each(|side| self.set_castling_mut(*side, false));
+        }
 
-        if is_double_move {
+        // Store en passant passing square
+        if movement.kind == MoveKind::DoublePush {
             let passing_square = if color == Color::White {
                 movement.to_square.down(1).unwrap()
             } else {
@@ -404,6 +639,9 @@ impl Board {
             self.en_passant = None;
         }
 
+        // Remove the piece from its old position
+        self.remove_mut(movement.from_square);
+
         // NOTE: Not checking rank is ok! this function is undefined for invalid moves. <o/
         if let Some(promotion) = movement.promote {
             self.replace_mut(promotion, movement.to_square);
@@ -411,20 +649,31 @@ impl Board {
             self.replace_mut(piece, movement.to_square);
         }
 
-        // Piece independent logic
+        // Switch side to move
+        self.side_to_move = self.side_to_move.other();
+
+        self.count_check_mut(color);
+    }
 
-        // Remove the piece from its old position
-        self.pieces[piece as usize].flip_mut(movement.from_square);
+    // The king and rook can start anywhere in Chess960, even on each other's
+    // destination, so take both off the board before putting them back.
+    fn castle_mut(&mut self, castling: CastlingSide, movement: &Movement) {
+        let color = self.side_to_move;
+        let king = castling.king_destination();
+        let rook = castling.rook_destination();
 
-        // Move the piece in the color grid
-        self.color_combined[color as usize].flip_mut(movement.from_square);
-        self.color_combined[color as usize].flip_mut(movement.to_square);
+        self.remove_mut(movement.from_square);
+        self.remove_mut(movement.to_square);
+        self.put_mut(Piece::King, color, king);
+        self.put_mut(Piece::Rook, color, rook);
 
-        // Switch side to move
-        self.side_to_move = self.side_to_move.other();
+        for &side in CastlingSide::of_color(color).iter() {
+            self.set_castling_mut(side, false);
+        }
 
-        // Update attackers (todo: inline for speed)
-        self.update_attackers();
+        self.en_passant = None;
+        self.side_to_move = color.other();
+        self.count_check_mut(color);
     }
 
     // TODO: Test
@@ -527,7 +776,77 @@ mod tests {
 
     #[test]
     fn test_from_fen_invalid() {
-        assert!(Board::from_fen("").is_none());
+        assert!(Board::from_fen("").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let validate = |fen: &str| Board::from_fen(fen).unwrap().validate();
+
+        assert_eq!(validate(STARTING_FEN), Ok(()));
+        assert_eq!(
+            validate("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+            Ok(())
+        );
+
+        assert_eq!(
+            validate("4k3/8/8/8/8/8/8/8 w - - 0 1"),
+            Err(PositionError::WrongKingCount {
+                color: Color::White,
+                count: 0
+            })
+        );
+        assert_eq!(
+            validate("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"),
+            Err(PositionError::WrongKingCount {
+                color: Color::White,
+                count: 2
+            })
+        );
+        assert_eq!(
+            validate("4k2P/8/8/8/8/8/8/4K3 w - - 0 1"),
+            Err(PositionError::PawnOnBackRank(sq("h8")))
+        );
+        assert_eq!(validate("4k3/8/8/8/8/8/8/4K2R w - - 0 1"), Ok(()));
+        assert_eq!(
+            validate("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"),
+            Err(PositionError::OpponentInCheck)
+        );
+        assert_eq!(
+            validate("4k3/8/8/8/8/8/4P3/4K3 b - e3 0 1"),
+            Err(PositionError::InvalidEnPassant(sq("e3")))
+        );
+        assert_eq!(
+            validate("4k3/8/8/8/4P3/8/8/4K3 b - d3 0 1"),
+            Err(PositionError::InvalidEnPassant(sq("d3")))
+        );
+
+        // Nobody needs a king in antichess
+        let mut board = Board::from_fen("8/8/8/8/8/8/p7/1N6 w - - 0 1").unwrap();
+        board.set_variant(Variant::Antichess);
+        assert_eq!(board.validate(), Ok(()));
+        board.side_to_move = Color::Black;
+        assert_eq!(board.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_try_make_move() {
+        let mut board = Board::from_start_pos();
+        let mv = |lan: &str| Movement::from_notation(lan).unwrap();
+
+        assert!(board.is_legal(&mv("e2e4")));
+        assert!(!board.is_legal(&mv("e2e5")));
+        assert!(!board.is_legal(&mv("e7e5")));
+
+        assert_eq!(
+            board.try_make_move(&mv("e1g1")),
+            Err(IllegalMove(mv("e1g1")))
+        );
+        assert_eq!(board, Board::from_start_pos());
+
+        // from_notation doesn't know the kind, try_make_move works it out
+        board.try_make_move(&mv("e2e4")).unwrap();
+        assert_eq!(board.en_passant, Some(sq("e3")));
     }
 
     #[test]
@@ -538,7 +857,7 @@ mod tests {
         assert_eq!(b.castling, 0b1111);
         assert_eq!(b.side_to_move, Color::White);
 
-        b.make_move_mut(&Movement::from_notation("e2e4").expect("movement is valid"));
+        b.make_move_mut(&b.parse_move("e2e4").expect("movement is valid"));
         b.assert_valid();
 
         // Just moved a pawn forward 2, so en_passant
@@ -555,7 +874,7 @@ mod tests {
         let mut b = Board::from_fen("1nbqkbnr/rP1ppppp/p1p5/8/8/8/1PPPPPPP/RNBQKBNR w KQk - 1 5")
             .expect("before promotion fen is valid");
 
-        b.make_move_mut(&Movement::from_notation("b7c8q").expect("movement is valid"));
+        b.make_move_mut(&b.parse_move("b7c8q").expect("movement is valid"));
         b.assert_valid();
 
         let b7 = Square::new(6, 1);
@@ -572,7 +891,7 @@ mod tests {
             .unwrap();
         eprintln!("{}", b);
 
-        let movement = &Movement::from_notation("e4d5").unwrap();
+        let movement = &b.parse_move("e4d5").unwrap();
         b.make_move_mut(movement);
         b.assert_valid();
 
@@ -585,7 +904,7 @@ mod tests {
         let mut b =
             Board::from_fen("rnbqkbnr/ppp2ppp/8/3P4/8/2Np4/PP2PPPP/R1BQKBNR w KQkq - 0 1").unwrap();
 
-        let movement = &Movement::from_notation("e2d3").unwrap();
+        let movement = &b.parse_move("e2d3").unwrap();
         b.make_move_mut(movement);
 
         b.assert_valid();
@@ -602,7 +921,7 @@ mod tests {
         let mut board =
             Board::from_fen("rnbqk1nr/ppp2ppp/3b4/3p4/8/3B1N2/PPPP1PPP/RNBQK2R w KQkq - 2 5")
                 .unwrap();
-        board.make_move_mut(&Movement::from_notation("e1g1").unwrap());
+        board.make_move_mut(&board.parse_move("e1g1").unwrap());
 
         assert_eq!(board.piece_on(Square::from_notation("e1").unwrap()), None);
         assert_eq!(
@@ -619,23 +938,74 @@ mod tests {
         assert!(!board.can_castle_unchecked(CastlingSide::WhiteQueenside));
     }
 
+    #[test]
+    fn test_make_move_castle_960() {
+        // King on f1 and rook on g1, so they swap squares
+        let mut board =
+            Board::from_fen("nrbqbkrn/pppppppp/8/8/8/8/PPPPPPPP/NRBQBKRN w GBgb - 0 1").unwrap();
+        assert_eq!(board.castling_rook(CastlingSide::WhiteKingside), sq("g1"));
+        assert_eq!(board.castling_rook(CastlingSide::WhiteQueenside), sq("b1"));
+
+        // f1g1 would be a normal king move, so only king takes rook works here
+        let castle = board.parse_move("f1g1").unwrap();
+        assert_eq!(castle.kind, MoveKind::Castle(CastlingSide::WhiteKingside));
+        assert_eq!(castle.to_uci(true), "f1g1");
+        assert_eq!(castle.to_notation(), "f1g1");
+        assert!(board.is_legal(&castle));
+
+        board.make_move_mut(&castle);
+        board.assert_valid();
+        assert_eq!(board.piece_on(sq("g1")), Some(Piece::King));
+        assert_eq!(board.piece_on(sq("f1")), Some(Piece::Rook));
+        assert_eq!(
+            board.to_shredder_fen(),
+            "nrbqbkrn/pppppppp/8/8/8/8/PPPPPPPP/NRBQBRKN b gb - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_castling_rights_lost_on_capture() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        board.make_move_mut(&board.parse_move("a1a8").unwrap());
+        assert_eq!(board.to_fen(), "R3k2r/8/8/8/8/8/8/4K2R b Kk - 0 1");
+    }
+
+    #[test]
+    fn test_fen_960() {
+        let shredder = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 0 1";
+        let xfen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 0 1";
+        let board = Board::from_fen(shredder).unwrap();
+        assert_eq!(board.to_shredder_fen(), shredder);
+        assert_eq!(board.to_fen(), xfen);
+
+        // Only one rook on each side, so X-FEN uses KQkq
+        let board = Board::from_fen("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1").unwrap();
+        assert_eq!(board.to_fen(), "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1");
+        assert_eq!(Board::from_fen(&board.to_fen()).unwrap(), board);
+
+        // Two rooks on the h side, so the inner one needs its file
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3KRR1 w FA - 0 1").unwrap();
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/R3KRR1 w FQ - 0 1");
+        assert_eq!(Board::from_fen(&board.to_fen()).unwrap(), board);
+    }
+
     #[test]
     fn test_make_move_remove_castling() {
         let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
 
-        board.make_move_mut(&Movement::from_notation("a1a2").unwrap());
+        board.make_move_mut(&board.parse_move("a1a2").unwrap());
         assert!(!board.can_castle_unchecked(CastlingSide::WhiteQueenside));
         assert!(board.can_castle_unchecked(CastlingSide::WhiteKingside));
 
-        board.make_move_mut(&Movement::from_notation("h8h7").unwrap());
+        board.make_move_mut(&board.parse_move("h8h7").unwrap());
         assert!(!board.can_castle_unchecked(CastlingSide::BlackKingside));
         assert!(board.can_castle_unchecked(CastlingSide::BlackQueenside));
 
-        board.make_move_mut(&Movement::from_notation("e1f2").unwrap());
+        board.make_move_mut(&board.parse_move("e1f2").unwrap());
         assert!(!board.can_castle_unchecked(CastlingSide::WhiteKingside));
         assert!(!board.can_castle_unchecked(CastlingSide::WhiteQueenside));
 
-        board.make_move_mut(&Movement::from_notation("e8d7").unwrap());
+        board.make_move_mut(&board.parse_move("e8d7").unwrap());
         assert!(!board.can_castle_unchecked(CastlingSide::BlackKingside));
         assert!(!board.can_castle_unchecked(CastlingSide::BlackQueenside));
     }
@@ -644,9 +1014,9 @@ mod tests {
     fn test_make_move_bishop_en_passant() {
         let mut board = Board::from_start_pos();
 
-        board.make_move_mut(&Movement::from_notation("e2e4").unwrap());
-        board.make_move_mut(&Movement::from_notation("a7a5").unwrap());
-        board.make_move_mut(&Movement::from_notation("f1a6").unwrap());
+        board.make_move_mut(&board.parse_move("e2e4").unwrap());
+        board.make_move_mut(&board.parse_move("a7a5").unwrap());
+        board.make_move_mut(&board.parse_move("f1a6").unwrap());
 
         let on_a5 = board.piece_on(Square::from_notation("a5").unwrap());
         let on_a6 = board.piece_on(Square::from_notation("a6").unwrap());
@@ -655,19 +1025,121 @@ mod tests {
         assert_eq!(on_a6, Some(Piece::Bishop));
     }
 
+    #[test]
+    fn test_parse_move_kinds() {
+        let board = Board::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+        )
+        .unwrap();
+        let kind = |lan: &str| board.parse_move(lan).unwrap().kind;
+
+        assert_eq!(kind("b4a3"), MoveKind::EnPassant);
+        assert_eq!(kind("b4c3"), MoveKind::Capture(Piece::Knight));
+        assert_eq!(kind("e8g8"), MoveKind::Castle(CastlingSide::BlackKingside));
+        assert_eq!(kind("e8c8"), MoveKind::Castle(CastlingSide::BlackQueenside));
+        assert_eq!(kind("c7c5"), MoveKind::DoublePush);
+        assert_eq!(kind("b4b3"), MoveKind::Quiet);
+
+        let mv = board.parse_move("b4a3").unwrap();
+        assert!(board.is_capture(&mv));
+        assert!(mv.is_capture() && mv.is_en_passant());
+        assert_eq!(mv.captured_piece(), Some(Piece::Pawn));
+    }
+
+    #[test]
+    fn test_make_move_en_passant() {
+        let mut board = Board::from_fen("k7/8/8/8/Pp6/8/8/K7 b - a3 0 1").unwrap();
+        board.make_move_mut(&board.parse_move("b4a3").unwrap());
+        board.assert_valid();
+
+        assert_eq!(board.piece_on(sq("a4")), None);
+        assert_eq!(board.piece_on(sq("a3")), Some(Piece::Pawn));
+    }
+
     #[test]
     fn test_make_move_en_passant_cleared() {
         // en-passant should be cleared every move.
         let mut board = Board::from_start_pos();
-        board.make_move_mut(&Movement::from_notation("e2e4").unwrap());
+        board.make_move_mut(&board.parse_move("e2e4").unwrap());
         assert_eq!(board.en_passant, Some(Square::from_notation("e3").unwrap()));
 
         // Knight move is a better test if the pawn function clears en-passant,
         // it would hide on another pawn move.
-        board.make_move_mut(&Movement::from_notation("g8f6").unwrap());
+        board.make_move_mut(&board.parse_move("g8f6").unwrap());
         assert_eq!(board.en_passant, None);
     }
 
+    // Moves straight from notation are Quiet and castle onto the king's
+    // destination, debug builds catch them before they corrupt the board
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "unresolved move")]
+    fn test_make_move_unresolved_castle() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        board.make_move_mut(&Movement::from_notation("e1g1").unwrap());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "unresolved move")]
+    fn test_make_move_unresolved_en_passant() {
+        let mut board = Board::from_fen("k7/8/8/8/Pp6/8/8/K7 b - a3 0 1").unwrap();
+        board.make_move_mut(&Movement::from_notation("b4a3").unwrap());
+    }
+
+    #[test]
+    fn test_mirror() {
+        let board =
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b Kq e3 0 1").unwrap();
+        assert_eq!(
+            board.mirror().to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w Qk e6 0 1"
+        );
+        assert_eq!(board.mirror().mirror(), board);
+    }
+
+    // Every kind of move should leave the mailbox matching the bitboards
+    fn walk_mailbox(board: &Board, depth: u16) {
+        board.assert_valid();
+        board.mirror().assert_valid();
+        if depth == 0 {
+            return;
+        }
+        for mv in crate::movegen::MoveGen::new_legal(board) {
+            walk_mailbox(&board.make_move(&mv), depth - 1);
+        }
+    }
+
+    #[test]
+    fn test_mailbox_in_sync() {
+        let positions = [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+                Variant::Standard,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                Variant::Standard,
+            ),
+            (
+                "nrbqbkrn/pppppppp/8/8/8/8/PPPPPPPP/NRBQBKRN w GBgb - 0 1",
+                Variant::Standard,
+            ),
+            (
+                "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R[Nn] w KQkq - 2 3",
+                Variant::Crazyhouse,
+            ),
+        ];
+        for (fen, variant) in positions.iter() {
+            let mut board = Board::from_fen(fen).unwrap();
+            board.set_variant(*variant);
+            walk_mailbox(&board, 2);
+
+            let packed = Board::from_packed(&board.to_packed()).unwrap();
+            packed.assert_valid();
+        }
+    }
+
     #[test]
     fn test_to_fen_startpos() {
         let board = Board::from_start_pos();
@@ -677,7 +1149,7 @@ mod tests {
     #[test]
     fn test_to_fen_e2e4() {
         let mut board = Board::from_start_pos();
-        board.make_move_mut(&Movement::from_notation("e2e4").unwrap());
+        board.make_move_mut(&board.parse_move("e2e4").unwrap());
         assert_eq!(
             board.to_fen(),
             "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
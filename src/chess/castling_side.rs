@@ -1,4 +1,4 @@
-use super::{Color, Movement, Square};
+use super::{Color, Square};
 use crate::bitboard::BitBoard;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -9,35 +9,17 @@ pub enum CastlingSide {
     BlackQueenside = 3,
 }
 
+// Sides are named after standard chess, in Chess960 kingside means the rook
+// starts on the h-file side of the king, and the pieces end up on the same
+// squares as in standard chess (king g1 rook f1, or king c1 rook d1).
+// Where the rook starts is in Board::castling_rook.
 impl CastlingSide {
-    #[inline]
-    pub fn from_movement(movement: &Movement) -> Option<Self> {
-        match movement {
-            Movement {
-                from_square: Square(4),
-                to_square: Square(6),
-                promote: None,
-            } => Some(Self::WhiteKingside),
-            Movement {
-                from_square: Square(4),
-                to_square: Square(2),
-                promote: None,
-            } => Some(Self::WhiteQueenside),
-
-            Movement {
-                from_square: Square(60),
-                to_square: Square(62),
-                promote: None,
-            } => Some(Self::BlackKingside),
-            Movement {
-                from_square: Square(60),
-                to_square: Square(58),
-                promote: None,
-            } => Some(Self::BlackQueenside),
-
-            _ => None,
-        }
-    }
+    pub const ALL: [CastlingSide; 4] = [
+        CastlingSide::WhiteKingside,
+        CastlingSide::WhiteQueenside,
+        CastlingSide::BlackKingside,
+        CastlingSide::BlackQueenside,
+    ];
 
     #[inline]
     pub fn of_color(color: Color) -> [Self; 2] {
@@ -48,14 +30,8 @@ impl CastlingSide {
     }
 
     #[inline]
-    pub fn from_rook_square(square: Square) -> Option<Self> {
-        match square {
-            Square(0) => Some(CastlingSide::WhiteQueenside),
-            Square(7) => Some(CastlingSide::WhiteKingside),
-            Square(56) => Some(CastlingSide::BlackQueenside),
-            Square(63) => Some(CastlingSide::BlackKingside),
-            _ => None,
-        }
+    pub fn new(color: Color, kingside: bool) -> Self {
+        Self::of_color(color)[!kingside as usize]
     }
 
     #[inline]
@@ -68,44 +44,60 @@ impl CastlingSide {
         }
     }
 
-    // PAIN AND SUFFERING
     #[inline]
-    pub fn get_rook_movement(&self) -> Movement {
-        match self {
-            CastlingSide::WhiteKingside => Movement::new(Square(7), Square(5), None),
-            CastlingSide::WhiteQueenside => Movement::new(Square(0), Square(3), None),
-            CastlingSide::BlackKingside => Movement::new(Square(63), Square(61), None),
-            CastlingSide::BlackQueenside => Movement::new(Square(56), Square(59), None),
-        }
+    pub fn is_kingside(&self) -> bool {
+        matches!(
+            self,
+            CastlingSide::WhiteKingside | CastlingSide::BlackKingside
+        )
     }
 
     #[inline]
-    pub fn get_king_movement(&self) -> Movement {
-        match self {
-            CastlingSide::WhiteKingside => Movement::new(Square(4), Square(6), None),
-            CastlingSide::WhiteQueenside => Movement::new(Square(4), Square(2), None),
-            CastlingSide::BlackKingside => Movement::new(Square(60), Square(62), None),
-            CastlingSide::BlackQueenside => Movement::new(Square(60), Square(58), None),
+    pub fn back_rank(&self) -> u8 {
+        match self.color() {
+            Color::White => 0,
+            Color::Black => 7,
         }
     }
 
+    // Where the rook starts in standard chess
     #[inline]
-    pub fn get_castling_middle(&self) -> BitBoard {
-        match self {
-            CastlingSide::WhiteKingside => BitBoard(0x60),
-            CastlingSide::WhiteQueenside => BitBoard(0xe),
-            CastlingSide::BlackKingside => BitBoard(0x6000000000000000),
-            CastlingSide::BlackQueenside => BitBoard(0xe00000000000000),
+    pub fn default_rook_file(&self) -> u8 {
+        if self.is_kingside() {
+            7
+        } else {
+            0
         }
     }
 
     #[inline]
-    pub fn get_castling_not_attacked(&self) -> BitBoard {
-        match self {
-            CastlingSide::WhiteKingside => BitBoard(0x70),
-            CastlingSide::WhiteQueenside => BitBoard(0x1c),
-            CastlingSide::BlackKingside => BitBoard(0x7000000000000000),
-            CastlingSide::BlackQueenside => BitBoard(0x1c00000000000000),
-        }
+    pub fn king_destination(&self) -> Square {
+        Square::new(self.back_rank(), if self.is_kingside() { 6 } else { 2 })
+    }
+
+    #[inline]
+    pub fn rook_destination(&self) -> Square {
+        Square::new(self.back_rank(), if self.is_kingside() { 5 } else { 3 })
+    }
+}
+
+// Squares on a rank between two squares, both included
+pub fn rank_span(a: Square, b: Square) -> BitBoard {
+    debug_assert_eq!(a.rank(), b.rank());
+    let (lo, hi) = if a.0 <= b.0 { (a.0, b.0) } else { (b.0, a.0) };
+    BitBoard((u64::MAX >> (63 - hi)) & (u64::MAX << lo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_span() {
+        let sq = |s: &str| Square::from_notation(s).unwrap();
+        assert_eq!(rank_span(sq("e1"), sq("g1")), BitBoard(0x70));
+        assert_eq!(rank_span(sq("d8"), sq("b8")), BitBoard(0xe00000000000000));
+        assert_eq!(rank_span(sq("a1"), sq("a1")), BitBoard(0x1));
+        assert_eq!(rank_span(sq("a8"), sq("h8")), BitBoard(0xff00000000000000));
     }
 }
@@ -0,0 +1,415 @@
+use crate::chess::*;
+use std::error::Error;
+use std::fmt;
+
+// https://www.chessprogramming.org/Forsyth-Edwards_Notation
+//
+// Ranks in errors are indexes like everywhere else, 0 is the first rank.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(&'static str),
+    TooManyFields,
+    WrongRankCount(usize),
+    RankTooLong { rank: u8 },
+    RankTooShort { rank: u8 },
+    InvalidPiece { square: Square, found: char },
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    CastlingWithoutPieces(CastlingSide),
+    InvalidEnPassant(String),
+    InvalidClock(String),
+    InvalidPocket(String),
+    InvalidChecks(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {}", field),
+            FenError::TooManyFields => write!(f, "too many fields"),
+            FenError::WrongRankCount(n) => write!(f, "expected 8 ranks, got {}", n),
+            FenError::RankTooLong { rank } => {
+                write!(f, "rank {} has more than 8 squares", rank + 1)
+            }
+            FenError::RankTooShort { rank } => {
+                write!(f, "rank {} has less than 8 squares", rank + 1)
+            }
+            FenError::InvalidPiece { square, found } => {
+                write!(f, "invalid piece '{}' on {}", found, square)
+            }
+            FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling rights '{}'", s),
+            FenError::CastlingWithoutPieces(side) => {
+                write!(f, "can't castle {:?}, the king or rook has moved", side)
+            }
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::InvalidClock(s) => write!(f, "invalid move counter '{}'", s),
+            FenError::InvalidPocket(s) => write!(f, "invalid pocket '{}'", s),
+            FenError::InvalidChecks(s) => write!(f, "invalid check counter '{}'", s),
+        }
+    }
+}
+
+impl Error for FenError {}
+
+// Crazyhouse pockets come after the pieces, either in brackets
+// (RNBQKBNR[Qp]) or as a ninth rank (RNBQKBNR/Qp).
+fn parse_pocket(board: &mut Board, pocket: &str) -> Result<(), FenError> {
+    for c in pocket.chars() {
+        let piece = Piece::from_char(c.to_ascii_lowercase())
+            .filter(|p| *p != Piece::King)
+            .ok_or_else(|| FenError::InvalidPocket(pocket.to_string()))?;
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        board.pockets[color as usize][piece as usize] += 1;
+    }
+    Ok(())
+}
+
+fn parse_placement(board: &mut Board, placement: &str) -> Result<(), FenError> {
+    let mut placement = placement;
+    if let Some(start) = placement.find('[') {
+        let pocket = placement[start..]
+            .strip_prefix('[')
+            .and_then(|p| p.strip_suffix(']'))
+            .ok_or_else(|| FenError::InvalidPocket(placement[start..].to_string()))?;
+        parse_pocket(board, pocket)?;
+        placement = &placement[..start];
+    }
+
+    let mut ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() == 9 {
+        parse_pocket(board, ranks.pop().unwrap())?;
+    }
+    if ranks.len() != 8 {
+        return Err(FenError::WrongRankCount(ranks.len()));
+    }
+
+    for (i, rank) in ranks.iter().enumerate() {
+        // Fen starts at the top (black side)
+        let rank_index = 7 - i as u8;
+        let mut file_index: u8 = 0;
+
+        for piece_char in rank.chars() {
+            // Promoted piece in crazyhouse, goes after the piece
+            if piece_char == '~' && file_index > 0 {
+                board
+                    .promoted
+                    .flip_mut(Square::new(rank_index, file_index - 1));
+                continue;
+            }
+
+            if file_index >= 8 {
+                return Err(FenError::RankTooLong { rank: rank_index });
+            }
+
+            if let Some(empty) = piece_char.to_digit(10).filter(|&n| n > 0) {
+                if file_index + empty as u8 > 8 {
+                    return Err(FenError::RankTooLong { rank: rank_index });
+                }
+                file_index += empty as u8;
+                continue;
+            }
+
+            let sq = Square::new(rank_index, file_index);
+            let piece = Piece::from_char(piece_char.to_ascii_lowercase()).ok_or(
+                FenError::InvalidPiece {
+                    square: sq,
+                    found: piece_char,
+                },
+            )?;
+            let color = if piece_char.is_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+
+            board.put_mut(piece, color, sq);
+            file_index += 1;
+        }
+
+        if file_index < 8 {
+            return Err(FenError::RankTooShort { rank: rank_index });
+        }
+    }
+
+    Ok(())
+}
+
+// KQkq, Shredder-FEN (HAha, rook files) or X-FEN (KQkq unless there's
+// another rook further out, then the file) for Chess960.
+fn parse_castling(board: &mut Board, castling: &str) -> Result<(), FenError> {
+    board.castling = 0;
+    if castling == "-" {
+        return Ok(());
+    }
+
+    for c in castling.chars() {
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let back_rank = match color {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+
+        // No king on the back rank means no castling, but we still need a side for the error
+        let ours = *board.color_combined(color);
+        let king = (*board.pieces(Piece::King) & ours)
+            .find(|sq| sq.rank() == back_rank)
+            .map(|sq| sq.file());
+        let king_file = king.unwrap_or(4);
+        let rooks: Vec<u8> = (*board.pieces(Piece::Rook) & ours)
+            .filter(|sq| sq.rank() == back_rank)
+            .map(|sq| sq.file())
+            .collect();
+
+        let (side, rook) = match c.to_ascii_lowercase() {
+            'k' => (
+                CastlingSide::new(color, true),
+                rooks.iter().copied().filter(|&f| f > king_file).max(),
+            ),
+            'q' => (
+                CastlingSide::new(color, false),
+                rooks.iter().copied().filter(|&f| f < king_file).min(),
+            ),
+            file @ 'a'..='h' => {
+                let file = file as u8 - b'a';
+                (
+                    CastlingSide::new(color, file > king_file),
+                    Some(file).filter(|f| rooks.contains(f)),
+                )
+            }
+            _ => return Err(FenError::InvalidCastling(castling.to_string())),
+        };
+
+        if board.can_castle_unchecked(side) {
+            // Same right twice
+            return Err(FenError::InvalidCastling(castling.to_string()));
+        }
+
+        // The king and rook have to be at home
+        match (king, rook) {
+            (Some(_), Some(rook)) => {
+                board.set_castling_mut(side, true);
+                board.rook_files[side as usize] = rook;
+            }
+            _ => return Err(FenError::CastlingWithoutPieces(side)),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_en_passant(board: &mut Board, en_passant: &str) -> Result<(), FenError> {
+    if en_passant == "-" {
+        return Ok(());
+    }
+
+    // Behind a pawn that just moved two squares, so depends on who moved
+    let rank = match board.side_to_move {
+        Color::White => 5,
+        Color::Black => 2,
+    };
+    match Square::from_notation(en_passant) {
+        Some(sq) if en_passant.len() == 2 && sq.rank() == rank => {
+            board.en_passant = Some(sq);
+            Ok(())
+        }
+        _ => Err(FenError::InvalidEnPassant(en_passant.to_string())),
+    }
+}
+
+// Three-check counters, either checks left after the en passant square
+// like lichess (3+3), or checks given after the move counters (+0+0).
+fn parse_checks(board: &mut Board, checks: &str) -> Result<(), FenError> {
+    let err = || FenError::InvalidChecks(checks.to_string());
+
+    let (given, counts) = match checks.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, checks),
+    };
+    let counts: Vec<u8> = counts
+        .split('+')
+        .map(|n| n.parse::<u8>().ok().filter(|&n| n <= 3))
+        .collect::<Option<_>>()
+        .ok_or_else(err)?;
+    if counts.len() != 2 {
+        return Err(err());
+    }
+
+    for (color, &n) in counts.iter().enumerate() {
+        board.checks[color] = if given { n } else { 3 - n };
+    }
+    Ok(())
+}
+
+impl Board {
+    // Only checks the fen itself, see validate for whether the position makes sense.
+    pub fn from_fen(s: &str) -> Result<Board, FenError> {
+        let mut board = Board::empty();
+        let mut fields = s.split_whitespace();
+
+        let placement = fields.next().ok_or(FenError::MissingField("pieces"))?;
+        parse_placement(&mut board, placement)?;
+
+        board.side_to_move = match fields.next() {
+            Some("w") => Color::White,
+            Some("b") => Color::Black,
+            Some(side) => return Err(FenError::InvalidSideToMove(side.to_string())),
+            None => return Err(FenError::MissingField("side to move")),
+        };
+
+        let castling = fields.next().ok_or(FenError::MissingField("castling"))?;
+        parse_castling(&mut board, castling)?;
+
+        let en_passant = fields.next().ok_or(FenError::MissingField("en passant"))?;
+        parse_en_passant(&mut board, en_passant)?;
+
+        // Three-check counters can go before or after the move counters
+        let mut fields: Vec<&str> = fields.collect();
+        if let Some(i) = fields.iter().position(|f| f.contains('+')) {
+            parse_checks(&mut board, fields.remove(i))?;
+        }
+
+        // We don't keep the halfmove clock or fullmove number, but they
+        // should still be numbers if they're there.
+        for clock in fields.iter().take(2) {
+            if clock.parse::<u32>().is_err() {
+                return Err(FenError::InvalidClock(clock.to_string()));
+            }
+        }
+        if fields.len() > 2 {
+            return Err(FenError::TooManyFields);
+        }
+
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn err(fen: &str) -> FenError {
+        Board::from_fen(fen).unwrap_err()
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        assert_eq!(err(""), FenError::MissingField("pieces"));
+        assert_eq!(
+            err("8/8/8/8/8/8/8/8"),
+            FenError::MissingField("side to move")
+        );
+        assert_eq!(
+            err("4k3/8/8/8/8/8/8/4K3 w -"),
+            FenError::MissingField("en passant")
+        );
+
+        // Move counters are optional
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").is_ok());
+        assert_eq!(
+            err("4k3/8/8/8/8/8/8/4K3 w - - 0 1 2"),
+            FenError::TooManyFields
+        );
+        assert_eq!(
+            err("4k3/8/8/8/8/8/8/4K3 w - - x 1"),
+            FenError::InvalidClock("x".to_string())
+        );
+    }
+
+    #[test]
+    fn test_bad_placement() {
+        assert_eq!(
+            err("4k3/8/8/8/8/8/4K3 w - - 0 1"),
+            FenError::WrongRankCount(7)
+        );
+        assert_eq!(
+            err("4k3/8/8/8/8/8/8/4K4 w - - 0 1"),
+            FenError::RankTooLong { rank: 0 }
+        );
+        assert_eq!(
+            err("4k3/9/8/8/8/8/8/4K3 w - - 0 1"),
+            FenError::RankTooLong { rank: 6 }
+        );
+        assert_eq!(
+            err("4k2/8/8/8/8/8/8/4K3 w - - 0 1"),
+            FenError::RankTooShort { rank: 7 }
+        );
+        assert_eq!(
+            err("4k3/8/8/3x4/8/8/8/4K3 w - - 0 1"),
+            FenError::InvalidPiece {
+                square: Square::from_notation("d5").unwrap(),
+                found: 'x'
+            }
+        );
+    }
+
+    #[test]
+    fn test_bad_fields() {
+        assert_eq!(
+            err("4k3/8/8/8/8/8/8/4K3 x - - 0 1"),
+            FenError::InvalidSideToMove("x".to_string())
+        );
+        assert_eq!(
+            err("r3k2r/8/8/8/8/8/8/R3K2R w KQkqK - 0 1"),
+            FenError::InvalidCastling("KQkqK".to_string())
+        );
+        assert_eq!(
+            err("r3k2r/8/8/8/8/8/8/R3K2R w KX - 0 1"),
+            FenError::InvalidCastling("KX".to_string())
+        );
+        assert_eq!(
+            err("r3k2r/8/8/8/8/8/8/R3K3 w KQkq - 0 1"),
+            FenError::CastlingWithoutPieces(CastlingSide::WhiteKingside)
+        );
+        assert_eq!(
+            err("r3k2r/8/8/8/8/8/8/R3K2R w KQkc - 0 1"),
+            FenError::CastlingWithoutPieces(CastlingSide::BlackQueenside)
+        );
+        assert_eq!(
+            err("4k3/8/8/8/4P3/8/8/4K3 b - e6 0 1"),
+            FenError::InvalidEnPassant("e6".to_string())
+        );
+        assert_eq!(
+            err("4k3/8/8/8/4P3/8/8/4K3 b - e33 0 1"),
+            FenError::InvalidEnPassant("e33".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            FenError::RankTooShort { rank: 7 }.to_string(),
+            "rank 8 has less than 8 squares"
+        );
+    }
+
+    #[test]
+    fn test_variant_fields() {
+        assert_eq!(
+            err("4k3/8/8/8/8/8/8/4K3[Kp] w - - 0 1"),
+            FenError::InvalidPocket("Kp".to_string())
+        );
+        assert_eq!(
+            err("4k3/8/8/8/8/8/8/4K3[Qp w - - 0 1"),
+            FenError::InvalidPocket("[Qp".to_string())
+        );
+        assert_eq!(
+            err("4k3/8/8/8/8/8/8/4K3 w - - 4+3 0 1"),
+            FenError::InvalidChecks("4+3".to_string())
+        );
+
+        // Checks left vs checks given
+        let left = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 2+3 0 1").unwrap();
+        let given = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1 +1+0").unwrap();
+        assert_eq!(left.checks, [1, 0]);
+        assert_eq!(left, given);
+    }
+}
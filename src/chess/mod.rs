@@ -3,16 +3,23 @@ pub const NUM_PIECES: usize = 6;
 
 pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
+mod attacks;
 mod board;
 mod castling_side;
 mod color;
+mod fen;
 mod movement;
+mod packed;
 mod piece;
 mod square;
+mod variant;
 
 pub use board::*;
 pub use castling_side::*;
 pub use color::*;
+pub use fen::*;
 pub use movement::*;
+pub use packed::*;
 pub use piece::*;
 pub use square::*;
+pub use variant::*;
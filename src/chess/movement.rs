@@ -1,14 +1,42 @@
 use std::fmt;
 
+use crate::chess::CastlingSide;
+use crate::chess::Color;
 use crate::chess::Piece;
 use crate::chess::Square;
 
+// What a move does besides moving a piece, set by the move generator
+// so make move doesn't have to work it out again. Promotions are in
+// Movement::promote, a promotion can also be a capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveKind {
+    Quiet,
+    DoublePush,
+    Castle(CastlingSide),
+    Capture(Piece),
+    EnPassant,
+    Drop(Piece), // Crazyhouse, from_square is the same as to_square
+}
+
 // Calling it Movement and not Move because "move" is a keyword
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Eq)]
 pub struct Movement {
     pub from_square: Square,
     pub to_square: Square,
     pub promote: Option<Piece>,
+    pub kind: MoveKind,
+}
+
+// The kind follows from the squares and the board, so it isn't compared.
+// That way a move parsed from notation equals the generated one.
+// Except for drops, where the piece isn't on the board yet.
+impl PartialEq for Movement {
+    fn eq(&self, other: &Self) -> bool {
+        self.from_square == other.from_square
+            && self.to_square == other.to_square
+            && self.promote == other.promote
+            && self.dropped() == other.dropped()
+    }
 }
 
 impl fmt::Display for Movement {
@@ -29,24 +57,46 @@ impl Movement {
             from_square,
             to_square,
             promote,
+            kind: MoveKind::Quiet,
         }
     }
 
+    pub fn with_kind(self, kind: MoveKind) -> Movement {
+        Movement { kind, ..self }
+    }
+
+    // The kind is always Quiet here, we don't know the board.
+    // Use Board::parse_move for moves that are going to be made.
     pub fn from_notation(lan: &str) -> Option<Movement> {
+        // Drops are P@e4, the piece is uppercase whoever is dropping it
+        if lan.get(1..2) == Some("@") {
+            let piece = Piece::from_char(lan.chars().next()?.to_ascii_lowercase())?;
+            let sq = Square::from_notation(lan.get(2..4)?)?;
+            if piece == Piece::King || lan.len() != 4 {
+                return None;
+            }
+            return Some(Movement::new(sq, sq, None).with_kind(MoveKind::Drop(piece)));
+        }
+
         let from_square = Square::from_notation(lan.get(0..2)?)?;
         let to_square = Square::from_notation(lan.get(2..4)?)?;
         let promote = lan.chars().nth(4).and_then(Piece::from_char);
 
-        Some(Movement {
-            from_square,
-            to_square,
-            promote,
-        })
+        Some(Movement::new(from_square, to_square, promote))
     }
 
+    // Standard notation, castling is written as the king moving two squares
+    // even though we store it as the king taking its rook.
     pub fn to_notation(&self) -> String {
+        if let MoveKind::Drop(piece) = self.kind {
+            return format!("{}@{}", piece.as_char_color(Color::White), self.to_square);
+        }
+
         let from_notation = self.from_square.to_notation();
-        let to_notation = self.to_square.to_notation();
+        let to_notation = match self.kind {
+            MoveKind::Castle(side) => side.king_destination().to_notation(),
+            _ => self.to_square.to_notation(),
+        };
 
         let mut lan = String::new();
         lan.push_str(&from_notation);
@@ -59,12 +109,63 @@ impl Movement {
         lan
     }
 
+    // UCI notation, in Chess960 castling is king takes rook (e1h1)
+    pub fn to_uci(&self, chess960: bool) -> String {
+        if chess960 && self.is_castle() {
+            format!("{}{}", self.from_square, self.to_square)
+        } else {
+            self.to_notation()
+        }
+    }
+
     pub fn hash(&self) -> u16 {
-        ((self.promote.unwrap_or(Piece::Pawn) as u16) << 12)
+        ((self.promote.or(self.dropped()).unwrap_or(Piece::Pawn) as u16) << 12)
             | ((self.from_square.0 as u16) << 6)
             | (self.to_square.0 as u16)
     }
 
+    #[inline]
+    pub fn is_capture(&self) -> bool {
+        matches!(self.kind, MoveKind::Capture(_) | MoveKind::EnPassant)
+    }
+
+    #[inline]
+    pub fn is_castle(&self) -> bool {
+        matches!(self.kind, MoveKind::Castle(_))
+    }
+
+    #[inline]
+    pub fn is_en_passant(&self) -> bool {
+        self.kind == MoveKind::EnPassant
+    }
+
+    #[inline]
+    pub fn is_drop(&self) -> bool {
+        matches!(self.kind, MoveKind::Drop(_))
+    }
+
+    #[inline]
+    pub fn dropped(&self) -> Option<Piece> {
+        match self.kind {
+            MoveKind::Drop(piece) => Some(piece),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn is_promotion(&self) -> bool {
+        self.promote.is_some()
+    }
+
+    #[inline]
+    pub fn captured_piece(&self) -> Option<Piece> {
+        match self.kind {
+            MoveKind::Capture(piece) => Some(piece),
+            MoveKind::EnPassant => Some(Piece::Pawn),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn vdelta(&self) -> i8 {
         self.to_square.rank() as i8 - self.from_square.rank() as i8
@@ -75,3 +176,54 @@ impl Movement {
         self.to_square.file() as i8 - self.from_square.file() as i8
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eq_ignores_kind() {
+        let parsed = Movement::from_notation("e1d2").unwrap();
+        let generated = parsed.clone().with_kind(MoveKind::Capture(Piece::Pawn));
+        assert_eq!(parsed, generated);
+        assert!(generated.is_capture() && !parsed.is_capture());
+    }
+
+    #[test]
+    fn test_castle_notation() {
+        let castle = Movement::from_notation("e1h1")
+            .unwrap()
+            .with_kind(MoveKind::Castle(CastlingSide::WhiteKingside));
+        assert_eq!(castle.to_notation(), "e1g1");
+        assert_eq!(castle.to_uci(false), "e1g1");
+        assert_eq!(castle.to_uci(true), "e1h1");
+
+        // Chess960, king on b8 and rook on a8
+        let castle = Movement::from_notation("b8a8")
+            .unwrap()
+            .with_kind(MoveKind::Castle(CastlingSide::BlackQueenside));
+        assert_eq!(castle.to_notation(), "b8c8");
+        assert_eq!(castle.to_uci(true), "b8a8");
+    }
+
+    #[test]
+    fn test_promotion_capture() {
+        let mv = Movement::from_notation("d7c8q")
+            .unwrap()
+            .with_kind(MoveKind::Capture(Piece::Bishop));
+        assert!(mv.is_promotion() && mv.is_capture());
+        assert_eq!(mv.captured_piece(), Some(Piece::Bishop));
+    }
+
+    #[test]
+    fn test_drop_notation() {
+        let mv = Movement::from_notation("N@f3").unwrap();
+        assert_eq!(mv.dropped(), Some(Piece::Knight));
+        assert_eq!(mv.from_square, mv.to_square);
+        assert_eq!(mv.to_notation(), "N@f3");
+
+        // Different pieces on the same square are different moves
+        assert_ne!(mv, Movement::from_notation("B@f3").unwrap());
+        assert_eq!(Movement::from_notation("K@f3"), None);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::bitboard::BitBoard;
+use crate::chess::*;
+
+// Compact encodings for datasets, books and caches.
+//
+// A board packs into 32 bytes:
+//   0..8    occupancy bitboard, little endian
+//   8..24   4 bit piece codes (piece | color << 3), in occupancy order,
+//           low nibble first. Never more than 32 pieces in a legal position.
+//   24      side to move (bit 0) and castling rights (bits 1 to 4)
+//   25      en passant square, 0xff for none
+//   26..28  castling rook files, 3 bits each by CastlingSide, little endian
+//   28..32  unused, zero
+//
+// Boards are standard chess only, variant state (pockets, check counters)
+// doesn't fit and isn't packed.
+
+pub const PACKED_BOARD_SIZE: usize = 32;
+const NO_EN_PASSANT: u8 = 0xff;
+
+impl Board {
+    pub fn to_packed(&self) -> [u8; PACKED_BOARD_SIZE] {
+        let mut packed = [0; PACKED_BOARD_SIZE];
+        let occupied = self.combined();
+        assert!(occupied.count_ones() <= 32, "too many pieces to pack");
+
+        packed[0..8].copy_from_slice(&occupied.0.to_le_bytes());
+
+        for (i, sq) in occupied.enumerate() {
+            let piece = self.piece_on(sq).unwrap() as u8;
+            let color = self.color_on(sq).unwrap() as u8;
+            packed[8 + i / 2] |= (piece | color << 3) << (4 * (i % 2));
+        }
+
+        packed[24] = self.side_to_move as u8 | self.castling << 1;
+        packed[25] = self.en_passant.map_or(NO_EN_PASSANT, |sq| sq.0);
+
+        let files = self
+            .rook_files
+            .iter()
+            .enumerate()
+            .fold(0u16, |acc, (i, &file)| acc | (file as u16) << (3 * i));
+        packed[26..28].copy_from_slice(&files.to_le_bytes());
+
+        packed
+    }
+
+    pub fn from_packed(packed: &[u8; PACKED_BOARD_SIZE]) -> Option<Board> {
+        let mut board = Board::empty();
+
+        let mut occupied = [0; 8];
+        occupied.copy_from_slice(&packed[0..8]);
+        let occupied = BitBoard(u64::from_le_bytes(occupied));
+        if occupied.count_ones() > 32 {
+            return None;
+        }
+
+        for (i, sq) in occupied.enumerate() {
+            let code = (packed[8 + i / 2] >> (4 * (i % 2))) & 0xf;
+            let piece = Piece::from_usize((code & 0b111) as usize)?;
+            let color = if code >> 3 == 0 {
+                Color::White
+            } else {
+                Color::Black
+            };
+
+            board.put_mut(piece, color, sq);
+        }
+
+        board.side_to_move = if packed[24] & 1 == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        board.castling = (packed[24] >> 1) & 0b1111;
+
+        let files = u16::from_le_bytes([packed[26], packed[27]]);
+        for (i, file) in board.rook_files.iter_mut().enumerate() {
+            *file = (files >> (3 * i)) as u8 & 0b111;
+        }
+        board.en_passant = match packed[25] {
+            NO_EN_PASSANT => None,
+            sq if sq < 64 => Some(Square(sq)),
+            _ => return None,
+        };
+
+        Some(board)
+    }
+
+    // The move back from its packed form, with its kind worked out on this board.
+    pub fn unpack_move(&self, packed: PackedMove) -> Movement {
+        self.resolve_move(&packed.to_movement())
+    }
+}
+
+impl Movement {
+    // Flags come from the move kind, so the move needs to have it set.
+    pub fn pack(&self) -> PackedMove {
+        let flags = if self.promote == Some(Piece::King) {
+            PackedMove::KING_PROMOTION
+        } else if let Some(promote) = self.promote {
+            let promote = promote as u16 - Piece::Knight as u16;
+            if self.is_capture() {
+                PackedMove::PROMOTION_CAPTURE | promote
+            } else {
+                PackedMove::PROMOTION | promote
+            }
+        } else {
+            match self.kind {
+                MoveKind::Quiet => PackedMove::QUIET,
+                MoveKind::DoublePush => PackedMove::DOUBLE_PUSH,
+                MoveKind::Castle(CastlingSide::WhiteKingside)
+                | MoveKind::Castle(CastlingSide::BlackKingside) => PackedMove::KING_CASTLE,
+                MoveKind::Castle(_) => PackedMove::QUEEN_CASTLE,
+                MoveKind::Capture(_) => PackedMove::CAPTURE,
+                MoveKind::EnPassant => PackedMove::EN_PASSANT,
+
+                // The from square is the to square, so use it for the piece
+                MoveKind::Drop(piece) => {
+                    return PackedMove(
+                        piece as u16 | (self.to_square.0 as u16) << 6 | PackedMove::DROP << 12,
+                    );
+                }
+            }
+        };
+
+        PackedMove(self.from_square.0 as u16 | (self.to_square.0 as u16) << 6 | flags << 12)
+    }
+}
+
+// A move in 16 bits, from (6) to (6) flags (4)
+// https://www.chessprogramming.org/Encoding_Moves#From-To_Based
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PackedMove(pub u16);
+
+impl PackedMove {
+    pub const QUIET: u16 = 0;
+    pub const DOUBLE_PUSH: u16 = 1;
+    pub const KING_CASTLE: u16 = 2;
+    pub const QUEEN_CASTLE: u16 = 3;
+    pub const CAPTURE: u16 = 4;
+    pub const EN_PASSANT: u16 = 5;
+    pub const DROP: u16 = 6; // Crazyhouse, the piece is in place of the from square
+
+    // Antichess, captures or not. Pawns only promote diagonally by capturing.
+    pub const KING_PROMOTION: u16 = 7;
+    pub const PROMOTION: u16 = 8; // plus the piece, knight to queen
+    pub const PROMOTION_CAPTURE: u16 = 12;
+
+    pub fn from_square(&self) -> Square {
+        Square((self.0 & 0x3f) as u8)
+    }
+
+    pub fn to_square(&self) -> Square {
+        Square((self.0 >> 6 & 0x3f) as u8)
+    }
+
+    pub fn flags(&self) -> u16 {
+        self.0 >> 12
+    }
+
+    pub fn is_capture(&self) -> bool {
+        match self.flags() {
+            Self::DROP => false,
+            Self::KING_PROMOTION => self.from_square().file() != self.to_square().file(),
+            flags => flags & Self::CAPTURE != 0,
+        }
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.flags() == Self::EN_PASSANT
+    }
+
+    pub fn is_castle(&self) -> bool {
+        self.flags() == Self::KING_CASTLE || self.flags() == Self::QUEEN_CASTLE
+    }
+
+    pub fn promote(&self) -> Option<Piece> {
+        if self.flags() == Self::KING_PROMOTION {
+            Some(Piece::King)
+        } else if self.flags() & Self::PROMOTION != 0 {
+            Piece::from_usize(Piece::Knight as usize + (self.flags() & 0b11) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_movement(&self) -> Movement {
+        if self.flags() == Self::DROP {
+            let piece = Piece::from_usize((self.0 & 0x3f) as usize).unwrap();
+            let to_square = self.to_square();
+            return Movement::new(to_square, to_square, None).with_kind(MoveKind::Drop(piece));
+        }
+
+        Movement::new(self.from_square(), self.to_square(), self.promote())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::MoveGen;
+
+    const FENS: [&str; 6] = [
+        STARTING_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "8/2p5/3p4/KP5r/1R2Pp1k/8/6P1/8 b - e3 0 1",
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+    ];
+
+    #[test]
+    fn test_board_roundtrip() {
+        for fen in FENS.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            let packed = board.to_packed();
+            assert_eq!(Board::from_packed(&packed).unwrap(), board, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_board_roundtrip_full() {
+        // Every piece, so all 32 nibbles are used
+        let board = Board::from_start_pos();
+        let packed = board.to_packed();
+        assert_eq!(&packed[0..8], &0xffff00000000ffffu64.to_le_bytes());
+        assert_eq!(Board::from_packed(&packed).unwrap(), board);
+    }
+
+    #[test]
+    fn test_bad_packed_board() {
+        let mut packed = Board::from_start_pos().to_packed();
+        packed[8] = 0x07; // piece 7 doesn't exist
+        assert!(Board::from_packed(&packed).is_none());
+    }
+
+    #[test]
+    fn test_move_roundtrip() {
+        for fen in FENS.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            for mv in MoveGen::new_legal(&board) {
+                let packed = mv.pack();
+                assert_eq!(packed.to_movement(), mv, "{} in {}", mv, fen);
+                assert_eq!(board.unpack_move(packed).kind, mv.kind);
+                assert_eq!(packed.is_capture(), mv.is_capture());
+            }
+        }
+    }
+
+    #[test]
+    fn test_move_flags() {
+        let kiwipete = Board::from_fen(FENS[1]).unwrap();
+
+        let mv = |s: &str| kiwipete.parse_move(s).unwrap().pack();
+
+        assert!(mv("b4a3").is_en_passant());
+        assert!(mv("e8g8").is_castle());
+        assert!(mv("e8c8").is_castle());
+        assert_eq!(mv("c7c5").flags(), PackedMove::DOUBLE_PUSH);
+
+        let drop = Movement::from_notation("N@e4").unwrap().pack();
+        assert_eq!(drop.flags(), PackedMove::DROP);
+        assert!(!drop.is_capture());
+        assert_eq!(drop.to_movement(), Movement::from_notation("N@e4").unwrap());
+
+        let promotion = Board::from_fen(FENS[2]).unwrap();
+        let mv = |s: &str| promotion.parse_move(s).unwrap().pack();
+        let packed = mv("d7c8q");
+        assert!(packed.is_capture());
+        assert_eq!(packed.promote(), Some(Piece::Queen));
+        assert_eq!(mv("d7d8n").promote(), Some(Piece::Knight));
+    }
+
+    #[test]
+    fn test_king_promotion() {
+        let mut board = Board::from_fen("1n6/P7/8/8/8/8/8/8 w - - 0 1").unwrap();
+        board.set_variant(Variant::Antichess);
+
+        let quiet = board.parse_move("a7a8k").unwrap();
+        let packed = quiet.pack();
+        assert_eq!(packed.promote(), Some(Piece::King));
+        assert!(!packed.is_capture());
+        assert_eq!(board.unpack_move(packed), quiet);
+
+        let capture = board.parse_move("a7b8k").unwrap();
+        let packed = capture.pack();
+        assert_eq!(packed.promote(), Some(Piece::King));
+        assert!(packed.is_capture());
+        assert_eq!(board.unpack_move(packed), capture);
+
+        // Every promotion gets back what it was
+        for mv in crate::movegen::MoveGen::new_legal(&board) {
+            let packed = mv.pack();
+            assert_eq!(board.unpack_move(packed), mv);
+            assert_eq!(packed.promote(), mv.promote);
+            assert_eq!(packed.is_capture(), mv.is_capture());
+        }
+    }
+}
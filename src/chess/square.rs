@@ -77,6 +77,13 @@ impl Square {
         Some(Square::new(self.rank(), self.file() + files))
     }
 
+    // Number of king moves between the squares (Chebyshev distance)
+    pub fn distance(&self, other: Square) -> u8 {
+        let ranks = (self.rank() as i8 - other.rank() as i8).abs();
+        let files = (self.file() as i8 - other.file() as i8).abs();
+        i8::max(ranks, files) as u8
+    }
+
     pub fn flip_vertical(&self) -> Square {
         Square::new(7 - self.rank(), self.file())
     }
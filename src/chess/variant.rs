@@ -0,0 +1,239 @@
+use crate::bitboard::BitBoard;
+use crate::chess::*;
+
+// The rules we're playing by, set through UCI_Variant. Everything is
+// standard chess unless the variant says otherwise:
+//
+// Three-check: giving a third check wins.
+// King of the Hill: getting your king to the center (d4, e4, d5, e5) wins.
+// Antichess: captures are forced, the king is just another piece, and
+// losing all your pieces (or having no moves) wins.
+// Crazyhouse: captured pieces go to your pocket and can be dropped back on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    ThreeCheck,
+    KingOfTheHill,
+    Antichess,
+    Crazyhouse,
+}
+
+// How the game ended, for the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    Win,
+    Loss,
+    Draw,
+}
+
+// In the order they're written in a fen pocket
+pub const POCKET_PIECES: [Piece; 5] = [
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+
+const CENTER: BitBoard = BitBoard(0x0000001818000000);
+
+impl Variant {
+    pub const ALL: [Variant; 5] = [
+        Variant::Standard,
+        Variant::ThreeCheck,
+        Variant::KingOfTheHill,
+        Variant::Antichess,
+        Variant::Crazyhouse,
+    ];
+
+    // The names lichess and most GUIs use
+    pub fn uci_name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::Antichess => "antichess",
+            Variant::Crazyhouse => "crazyhouse",
+        }
+    }
+
+    pub fn from_uci_name(name: &str) -> Option<Variant> {
+        Variant::ALL
+            .iter()
+            .copied()
+            .find(|v| v.uci_name() == name.to_ascii_lowercase())
+    }
+
+    // Can the king be checked (and does leaving it attacked make a move illegal)?
+    #[inline]
+    pub fn has_royal_king(&self) -> bool {
+        *self != Variant::Antichess
+    }
+}
+
+impl Board {
+    // Castling doesn't exist in antichess, everything else keeps the rights
+    // from the fen.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        if variant == Variant::Antichess {
+            for &side in CastlingSide::ALL.iter() {
+                self.set_castling_mut(side, false);
+            }
+        }
+    }
+
+    pub fn pocket(&self, color: Color, piece: Piece) -> u8 {
+        self.pockets[color as usize][piece as usize]
+    }
+
+    // Checks given by color, only counted in three-check
+    pub fn checks(&self, color: Color) -> u8 {
+        self.checks[color as usize]
+    }
+
+    // Games that ended because of the variant rules, before even looking
+    // at the moves. The move generator doesn't generate anything for these.
+    pub fn variant_result(&self) -> Option<GameResult> {
+        let them = self.side_to_move.other();
+        match self.variant {
+            Variant::ThreeCheck if self.checks(them) >= 3 => Some(GameResult::Loss),
+            Variant::KingOfTheHill
+                if (*self.pieces(Piece::King) & *self.color_combined(them) & CENTER)
+                    != BitBoard::empty() =>
+            {
+                Some(GameResult::Loss)
+            }
+            _ => None,
+        }
+    }
+
+    // Only makes sense when there are no legal moves.
+    pub fn game_result(&self) -> GameResult {
+        if let Some(result) = self.variant_result() {
+            result
+        } else if self.variant == Variant::Antichess {
+            GameResult::Win
+        } else if self.in_check() {
+            GameResult::Loss
+        } else {
+            GameResult::Draw
+        }
+    }
+
+    pub(super) fn drop_mut(&mut self, piece: Piece, square: Square) {
+        let color = self.side_to_move;
+        self.pockets[color as usize][piece as usize] -= 1;
+        self.put_mut(piece, color, square);
+
+        self.en_passant = None;
+        self.side_to_move = color.other();
+    }
+
+    // Captures go to the pocket, but a promoted piece goes back as a pawn,
+    // so we have to follow promoted pieces around.
+    pub(super) fn update_pockets_mut(&mut self, movement: &Movement) {
+        let color = self.side_to_move;
+        if let Some(captured) = movement.captured_piece() {
+            let captured = if self.promoted.get(movement.to_square) {
+                Piece::Pawn
+            } else {
+                captured
+            };
+            self.pockets[color as usize][captured as usize] += 1;
+        }
+
+        let was_promoted = self.promoted.get(movement.from_square);
+        self.promoted &= !BitBoard::from_square(movement.from_square);
+        self.promoted &= !BitBoard::from_square(movement.to_square);
+        if was_promoted || movement.is_promotion() {
+            self.promoted |= BitBoard::from_square(movement.to_square);
+        }
+    }
+
+    // Called after every move, color is who made it
+    pub(super) fn count_check_mut(&mut self, color: Color) {
+        if self.variant == Variant::ThreeCheck && self.in_check() {
+            self.checks[color as usize] += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(fen: &str, variant: Variant) -> Board {
+        let mut board = Board::from_fen(fen).unwrap();
+        board.set_variant(variant);
+        board
+    }
+
+    #[test]
+    fn test_uci_names() {
+        for variant in Variant::ALL.iter() {
+            assert_eq!(Variant::from_uci_name(variant.uci_name()), Some(*variant));
+        }
+        assert_eq!(Variant::from_uci_name("3Check"), Some(Variant::ThreeCheck));
+        assert_eq!(Variant::from_uci_name("atomic"), None);
+    }
+
+    #[test]
+    fn test_three_check() {
+        let mut b = board("7k/8/8/8/8/8/8/R6K w - - 0 1 +2+0", Variant::ThreeCheck);
+        assert_eq!(b.checks(Color::White), 2);
+        assert_eq!(b.variant_result(), None);
+
+        b.make_move_mut(&b.parse_move("a1a8").unwrap());
+        assert_eq!(b.checks(Color::White), 3);
+        assert_eq!(b.variant_result(), Some(GameResult::Loss));
+        assert_eq!(b.to_fen(), "R6k/8/8/8/8/8/8/7K b - - 0+3 0 1");
+    }
+
+    #[test]
+    fn test_king_of_the_hill() {
+        let mut b = board("8/8/8/8/8/3K4/8/7k w - - 0 1", Variant::KingOfTheHill);
+        b.make_move_mut(&b.parse_move("d3e4").unwrap());
+        assert_eq!(b.game_result(), GameResult::Loss);
+
+        // Same thing in standard chess is nothing
+        let mut b = board("8/8/8/8/8/3K4/8/7k w - - 0 1", Variant::Standard);
+        b.make_move_mut(&b.parse_move("d3e4").unwrap());
+        assert_eq!(b.variant_result(), None);
+    }
+
+    #[test]
+    fn test_crazyhouse_pockets() {
+        let mut b = board("8/1Pn4k/8/8/8/8/r7/4K3[] w - - 0 1", Variant::Crazyhouse);
+        for lan in ["b7b8q", "a2a8", "b8a8", "c7a8"].iter() {
+            b.make_move_mut(&b.parse_move(lan).unwrap());
+        }
+
+        // The rook goes in the pocket, but the promoted queen comes back as a pawn
+        assert_eq!(b.pocket(Color::White, Piece::Rook), 1);
+        assert_eq!(b.pocket(Color::Black, Piece::Pawn), 1);
+        assert_eq!(b.pocket(Color::Black, Piece::Queen), 0);
+        assert_eq!(b.to_fen(), "n7/7k/8/8/8/8/8/4K3[Rp] w - - 0 1");
+
+        b.make_move_mut(&b.parse_move("R@d1").unwrap());
+        assert_eq!(b.pocket(Color::White, Piece::Rook), 0);
+        assert_eq!(b.to_fen(), "n7/7k/8/8/8/8/8/3RK3[p] b - - 0 1");
+    }
+
+    #[test]
+    fn test_crazyhouse_fen() {
+        let fen = "rnbqkb1r/pppp1ppp/8/8/8/8/PPPP1PPP/RNBQKBNR~[NPp] w KQkq - 0 1";
+        let b = board(fen, Variant::Crazyhouse);
+        assert_eq!(b.pocket(Color::White, Piece::Knight), 1);
+        assert!(b.promoted.get(Square::from_notation("h1").unwrap()));
+        assert_eq!(b.to_fen(), fen);
+
+        // Some GUIs send the pocket as a ninth rank
+        let b2 = board(
+            "rnbqkb1r/pppp1ppp/8/8/8/8/PPPP1PPP/RNBQKBNR~/NPp w KQkq - 0 1",
+            Variant::Crazyhouse,
+        );
+        assert_eq!(b, b2);
+    }
+}
@@ -1,21 +1,37 @@
-use crate::chess::{Board, Color};
-use crate::movegen::{perft, MoveGen};
+use crate::bench;
+use crate::chess::{Board, Color, Variant};
+use crate::eval::{self, EvalParams, Network};
+use crate::movegen::perft_divide;
 use crate::search::Searcher;
 use crate::uci;
-use crate::uci::EngineMessage;
+use crate::uci::{EngineMessage, UciObserver};
 use std::io;
+use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
 
 pub struct Engine {
     position: Board,
     searcher: Searcher,
+
+    network: Option<Arc<Network>>, // From EvalFile, only used with UseNNUE
+    use_nnue: bool,
+    chess960: bool, // Castling moves are king takes rook
+    variant: Variant,
 }
 
 impl Engine {
     pub fn new() -> Engine {
+        let mut searcher = Searcher::new();
+        searcher.set_observer(Box::new(UciObserver { chess960: false }));
+
         Engine {
             position: Board::from_start_pos(),
-            searcher: Searcher::new(),
+            searcher,
+            network: None,
+            use_nnue: false,
+            chess960: false,
+            variant: Variant::Standard,
         }
     }
 
@@ -39,16 +55,32 @@ impl Engine {
     }
 
     fn perft(&self, depth: u16) {
-        let board = &self.position;
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
 
-        let mut nodes = 0;
-        for mv in MoveGen::new_legal(board) {
-            let n = perft(&board.make_move(&mv), depth - 1);
-            eprintln!("{}: {}", mv, n);
+        // No moves at depth 0, just the position itself
+        let mut nodes = if depth == 0 { 1 } else { 0 };
+        for (mv, n) in perft_divide(&self.position, depth, threads, None) {
+            println!("{}: {}", mv.to_uci(self.chess960), n);
             nodes += n;
         }
 
-        eprintln!("\nNodes searched: {}", nodes);
+        // Same as stockfish, so perft_debug can read either
+        println!("\nNodes searched: {}", nodes);
+    }
+
+    // Always the default eval, a bench is only comparable between runs
+    // with the same settings.
+    pub fn bench(opts: uci::Bench) {
+        if opts.threads > 1 {
+            println!("info string the search is single threaded, using 1 thread");
+        }
+
+        let result = bench::run(opts.depth, opts.hash);
+
+        println!("\n===========================");
+        println!("Total time (ms) : {}", result.time.as_millis());
+        println!("Nodes searched  : {}", result.nodes);
+        println!("Nodes/second    : {}", result.nps());
     }
 
     fn thinking_time(&self, opts: uci::Go) -> Duration {
@@ -76,12 +108,84 @@ impl Engine {
 
         let sr = if let Some(depth) = opts.depth {
             self.searcher.search_depth(&self.position, depth)
+        } else if let Some(nodes) = opts.nodes {
+            self.searcher.search_nodes(&self.position, nodes)
         } else {
             let thinking_time = self.thinking_time(opts);
             self.searcher.search_timed(&self.position, thinking_time)
         };
 
-        println!("bestmove {}", sr.mv);
+        println!("bestmove {}", sr.mv.to_uci(self.chess960));
+    }
+
+    fn set_option(&mut self, name: &str, value: Option<String>) {
+        match name {
+            // A params file written by the tuner, empty for the defaults
+            "EvalParams" => {
+                let params = match value.as_deref() {
+                    None | Some("") | Some("<empty>") => EvalParams::default(),
+                    Some(path) => match EvalParams::load(path) {
+                        Ok(params) => params,
+                        Err(e) => {
+                            println!("info string failed to load {}: {}", path, e);
+                            return;
+                        }
+                    },
+                };
+                self.searcher.set_params(params);
+            }
+
+            "EvalFile" => {
+                self.network = match value.as_deref() {
+                    None | Some("") | Some("<empty>") => None,
+                    Some(path) => match Network::load(path) {
+                        Ok(network) => Some(Arc::new(network)),
+                        Err(e) => {
+                            println!("info string failed to load {}: {}", path, e);
+                            None
+                        }
+                    },
+                };
+                self.update_nnue();
+            }
+            "UseNNUE" => {
+                self.use_nnue = value.as_deref() == Some("true");
+                self.update_nnue();
+            }
+
+            // Positions always take both castling notations, this is for our output
+            "UCI_Chess960" => {
+                self.chess960 = value.as_deref() == Some("true");
+                let observer = UciObserver {
+                    chess960: self.chess960,
+                };
+                self.searcher.set_observer(Box::new(observer));
+            }
+
+            // Applies to the current position and every position command after it
+            "UCI_Variant" => match value.as_deref().and_then(Variant::from_uci_name) {
+                Some(variant) => {
+                    self.variant = variant;
+                    self.position.set_variant(variant);
+                }
+                None => println!("info string unknown variant {:?}", value),
+            },
+
+            _ => eprintln!("unknown option {}", name),
+        }
+    }
+
+    fn update_nnue(&mut self) {
+        if self.use_nnue && self.network.is_none() {
+            // No network is built in, see eval/nnue.rs
+            println!(
+                "info string UseNNUE is on but no EvalFile is loaded and there's no built in \
+                 network, using the classical eval"
+            );
+        }
+
+        let network = self.network.clone().filter(|_| self.use_nnue);
+        self.searcher.set_nnue(network);
     }
 
     fn handle(&mut self, msg: uci::EngineMessage) {
@@ -89,21 +193,56 @@ impl Engine {
             EngineMessage::UCI => {
                 println!("id name Yobmef");
                 println!("id author PwnSquad");
+                println!("option name EvalParams type string default <empty>");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name UseNNUE type check default false");
+                println!("option name UCI_Chess960 type check default false");
+                println!(
+                    "option name UCI_Variant type combo default chess{}",
+                    Variant::ALL
+                        .iter()
+                        .map(|v| format!(" var {}", v.uci_name()))
+                        .collect::<String>()
+                );
                 println!("uciok");
             }
             EngineMessage::IsReady => println!("readyok"),
+            EngineMessage::SetOption { name, value } => self.set_option(&name, value),
             EngineMessage::Quit => std::process::exit(0),
 
             EngineMessage::Position(board, moves) => {
+                // Straight from the GUI, so don't trust any of it. On errors we
+                // keep the previous position, a search on garbage is worse.
                 let mut board: Board = board;
+                board.set_variant(self.variant);
+                if let Err(e) = board.validate() {
+                    println!("info string invalid position: {}", e);
+                    return;
+                }
                 for movement in moves {
-                    board.make_move_mut(&movement);
+                    if let Err(e) = board.try_make_move(&movement) {
+                        println!("info string {} in position command", e);
+                        return;
+                    }
                 }
                 eprintln!("current position:\n{}", board);
                 self.position = board;
             }
 
+            EngineMessage::Invalid(e) => println!("info string {}", e),
             EngineMessage::Go(opts) => self.go(opts),
+            EngineMessage::Bench(opts) => Engine::bench(opts),
+            EngineMessage::Eval => {
+                println!(
+                    "{}",
+                    eval::trace_with_params(self.searcher.params(), &self.position)
+                );
+                if let Some(network) = &self.network {
+                    let score = network.evaluate_board(&self.position)
+                        * self.position.side_to_move.polarize();
+                    println!("NNUE evaluation: {} (white side)", score);
+                }
+            }
 
             _ => {}
         }
@@ -140,6 +279,98 @@ mod tests {
         assert_eq!(engine.position, Board::from_fen(fen).unwrap());
     }
 
+    #[test]
+    fn test_position_960() {
+        let mut engine = Engine::new();
+        engine.handle(EngineMessage::SetOption {
+            name: "UCI_Chess960".to_string(),
+            value: Some("true".to_string()),
+        });
+        assert!(engine.chess960);
+
+        // Castling is king takes rook
+        let fen = "nrbqbkrn/pppppppp/8/8/8/8/PPPPPPPP/NRBQBKRN w GBgb - 0 1";
+        engine.handle(uci::parse(&format!("position fen {} moves f1g1", fen)).unwrap());
+        assert_eq!(
+            engine.position.to_shredder_fen(),
+            "nrbqbkrn/pppppppp/8/8/8/8/PPPPPPPP/NRBQBRKN b gb - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_position_illegal() {
+        let mut engine = Engine::new();
+        engine.handle(uci::parse("position startpos moves e2e4").unwrap());
+        let before = engine.position.clone();
+
+        // e7e5 is fine, e4e6 isn't, so nothing should change
+        engine.handle(uci::parse("position startpos moves e2e4 e7e5 e4e6").unwrap());
+        assert_eq!(engine.position, before);
+
+        // No white king
+        engine.handle(uci::parse("position fen 4k3/8/8/8/8/8/8/8 w - - 0 1").unwrap());
+        assert_eq!(engine.position, before);
+
+        // Not even a move, or not even a fen
+        let msg = uci::parse("position startpos moves e2e4 zz").unwrap();
+        assert!(matches!(msg, EngineMessage::Invalid(_)));
+        engine.handle(msg);
+        assert_eq!(engine.position, before);
+
+        engine.handle(uci::parse("position fen nonsense moves e2e4").unwrap());
+        assert_eq!(engine.position, before);
+    }
+
+    #[test]
+    fn test_position_variant() {
+        let mut engine = Engine::new();
+        engine.handle(EngineMessage::SetOption {
+            name: "UCI_Variant".to_string(),
+            value: Some("crazyhouse".to_string()),
+        });
+        assert_eq!(engine.variant, Variant::Crazyhouse);
+
+        // Take a pawn and drop it back
+        engine.handle(
+            uci::parse("position startpos moves e2e4 d7d5 e4d5 d8d5 b1c3 d5a5 P@e4").unwrap(),
+        );
+        assert_eq!(
+            engine.position.to_fen(),
+            "rnb1kbnr/ppp1pppp/8/q7/4P3/2N5/PPPP1PPP/R1BQKBNR[p] b KQkq - 0 1"
+        );
+
+        // Made up variants are ignored
+        engine.handle(EngineMessage::SetOption {
+            name: "UCI_Variant".to_string(),
+            value: Some("atomic".to_string()),
+        });
+        assert_eq!(engine.variant, Variant::Crazyhouse);
+    }
+
+    #[test]
+    fn test_eval_file() {
+        let path = std::env::temp_dir().join("yobmef_test_eval_file.nnue");
+        Network::random(1).save(&path).unwrap();
+
+        let mut engine = Engine::new();
+        let set = |engine: &mut Engine, name: &str, value: &str| {
+            engine.handle(EngineMessage::SetOption {
+                name: name.to_string(),
+                value: Some(value.to_string()),
+            })
+        };
+
+        set(&mut engine, "EvalFile", path.to_str().unwrap());
+        set(&mut engine, "UseNNUE", "true");
+        assert!(engine.network.is_some() && engine.use_nnue);
+
+        // A bad file unloads the network, we don't keep using a stale one
+        set(&mut engine, "EvalFile", "/does/not/exist.nnue");
+        assert!(engine.network.is_none());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn test_think_time() {
         // Reasonable bounds on thinking time
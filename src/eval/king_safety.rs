@@ -0,0 +1,144 @@
+use super::mobility::piece_attacks;
+use super::{EvalParams, Tapered};
+use crate::bitboard::BitBoard;
+use crate::chess::{Board, Color, Piece, Square};
+use crate::movegen;
+
+// King safety, mostly a midgame concern. We look at the pawns in front of
+// the king, open files next to it, and how many enemy pieces attack the
+// squares around it (the king zone).
+// https://www.chessprogramming.org/King_Safety
+
+fn shelter(params: &EvalParams, board: &Board, color: Color) -> Tapered {
+    let king = board.king(color);
+    let pawns = *board.pieces(Piece::Pawn);
+    let our_pawns = pawns & *board.color_combined(color);
+
+    let mut score = Tapered::default();
+
+    let first_file = king.file().saturating_sub(1);
+    let last_file = u8::min(king.file() + 1, 7);
+    for file in first_file..=last_file {
+        let file_bb = BitBoard::file_mask(file);
+
+        if (pawns & file_bb).count_ones() == 0 {
+            score += params.open_file;
+        } else if (our_pawns & file_bb).count_ones() == 0 {
+            score += params.semi_open_file;
+        }
+
+        let ahead = |ranks: u8| match color {
+            Color::White => king.up(ranks),
+            Color::Black => king.down(ranks),
+        };
+        let on_file = |sq: Square| Square::new(sq.rank(), file);
+
+        if let Some(sq) = ahead(1).map(on_file) {
+            if our_pawns.get(sq) {
+                score += params.shield_close;
+            } else if let Some(sq) = ahead(2).map(on_file) {
+                if our_pawns.get(sq) {
+                    score += params.shield_far;
+                }
+            }
+        }
+    }
+
+    score
+}
+
+fn king_attacks(params: &EvalParams, board: &Board, color: Color) -> Tapered {
+    let king = board.king(color);
+    let zone = movegen::king_attacks(king) | BitBoard::from_square(king);
+    let occupancy = board.combined();
+    let theirs = *board.color_combined(color.other());
+
+    let mut attackers = 0;
+    let mut units = 0;
+    for &piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen].iter() {
+        for sq in *board.pieces(piece) & theirs {
+            let hits = (piece_attacks(piece, sq, occupancy) & zone).count_ones() as i32;
+            if hits > 0 {
+                attackers += 1;
+                units += params.attacker_weight[piece as usize] * hits;
+            }
+        }
+    }
+
+    // A lone piece can rarely mate
+    if attackers < 2 {
+        return Tapered::default();
+    }
+
+    let table = &params.king_attack;
+    table[usize::min(units as usize, table.len() - 1)]
+}
+
+pub fn king_safety_for_color(params: &EvalParams, board: &Board, color: Color) -> Tapered {
+    // Antichess kings can be captured
+    if (*board.pieces(Piece::King) & *board.color_combined(color)) == BitBoard::empty() {
+        return Tapered::default();
+    }
+
+    shelter(params, board, color) + king_attacks(params, board, color)
+}
+
+// King safety score from white's perspective
+pub fn evaluate(params: &EvalParams, board: &Board) -> Tapered {
+    king_safety_for_color(params, board, Color::White)
+        - king_safety_for_color(params, board, Color::Black)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn test_shelter() {
+        let sheltered = board("4k3/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let pushed = board("4k3/8/8/8/8/6PP/5P2/6K1 w - - 0 1");
+        let naked = board("4k3/8/8/8/8/8/PPP5/6K1 w - - 0 1");
+
+        let p = EvalParams::DEFAULT;
+        let sheltered = shelter(&p, &sheltered, Color::White);
+        let pushed = shelter(&p, &pushed, Color::White);
+        let naked = shelter(&p, &naked, Color::White);
+
+        assert_eq!(sheltered, p.shield_close * 3);
+        assert_eq!(pushed, p.shield_close + p.shield_far * 2);
+        assert_eq!(naked, p.open_file * 3);
+    }
+
+    #[test]
+    fn test_single_attacker_ignored() {
+        let b = board("4k3/8/8/8/8/5q2/5PPP/6K1 w - - 0 1");
+        assert_eq!(
+            king_attacks(&EvalParams::DEFAULT, &b, Color::White),
+            Tapered::default()
+        );
+    }
+
+    #[test]
+    fn test_king_attacks() {
+        let calm = board("6k1/5ppp/8/8/8/8/5PPP/q1r3K1 w - - 0 1");
+        let attacked = board("6k1/5ppp/8/8/8/7n/5PPq/6K1 w - - 0 1");
+
+        let p = EvalParams::DEFAULT;
+        assert!(
+            king_attacks(&p, &attacked, Color::White).mg < king_attacks(&p, &calm, Color::White).mg
+        );
+    }
+
+    #[test]
+    fn test_symmetric() {
+        let b = board("r1bq1rk1/pp3ppp/2n2n2/3p4/3P4/2NB1N2/PP3PPP/R2QK2R w KQ - 0 1");
+        assert_eq!(
+            evaluate(&EvalParams::DEFAULT, &b),
+            -evaluate(&EvalParams::DEFAULT, &b.mirror())
+        );
+    }
+}
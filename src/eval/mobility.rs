@@ -0,0 +1,77 @@
+use super::pawns::pawn_attacks_setwise;
+use super::{EvalParams, Tapered};
+use crate::bitboard::BitBoard;
+use crate::chess::{Board, Color, Piece, Square, NUM_PIECES};
+use crate::movegen;
+
+// Mobility, how many safe squares each piece can go to. Squares attacked by
+// enemy pawns are not safe, and neither are squares with our own pieces.
+// The score is linear around a baseline, so a piece with average mobility
+// scores about 0 and material stays in the piece values.
+
+// Safe squares of an average piece, pawns and kings aren't counted
+const MOBILITY_BASELINE: [i32; NUM_PIECES] = [0, 4, 7, 7, 14, 0];
+
+// Attacks of a piece (other than a pawn) on a square
+pub fn piece_attacks(piece: Piece, sq: Square, occupancy: BitBoard) -> BitBoard {
+    match piece {
+        Piece::Knight => movegen::knight_attacks(sq),
+        Piece::Bishop => movegen::bishop_attacks(sq, occupancy),
+        Piece::Rook => movegen::rook_attacks(sq, occupancy),
+        Piece::Queen => movegen::queen_attacks(sq, occupancy),
+        Piece::King => movegen::king_attacks(sq),
+        Piece::Pawn => panic!("use pawn_attacks for pawns"),
+    }
+}
+
+pub fn mobility_for_color(params: &EvalParams, board: &Board, color: Color) -> Tapered {
+    let occupancy = board.combined();
+    let ours = *board.color_combined(color);
+    let their_pawns = *board.pieces(Piece::Pawn) & *board.color_combined(color.other());
+    let safe = !ours & !pawn_attacks_setwise(their_pawns, color.other());
+
+    let mut score = Tapered::default();
+    for &piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen].iter() {
+        for sq in *board.pieces(piece) & ours {
+            let moves = (piece_attacks(piece, sq, occupancy) & safe).count_ones() as i32;
+            score += params.mobility[piece as usize] * (moves - MOBILITY_BASELINE[piece as usize]);
+        }
+    }
+
+    score
+}
+
+// Mobility score from white's perspective
+pub fn evaluate(params: &EvalParams, board: &Board) -> Tapered {
+    mobility_for_color(params, board, Color::White)
+        - mobility_for_color(params, board, Color::Black)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_rook_more_mobile() {
+        let open = Board::from_fen("4k3/8/8/8/3R4/8/8/4K3 w - - 0 1").unwrap();
+        let cornered = Board::from_fen("4k3/8/8/8/8/8/1P6/RN2K3 w - - 0 1").unwrap();
+        assert!(
+            evaluate(&EvalParams::DEFAULT, &open).mg > evaluate(&EvalParams::DEFAULT, &cornered).mg
+        );
+    }
+
+    #[test]
+    fn test_pawn_attacked_squares_unsafe() {
+        // The black pawns cover a2 and e2, the knight can still take them
+        let safe = Board::from_fen("4k3/8/8/8/8/8/8/2N1K3 w - - 0 1").unwrap();
+        let covered = Board::from_fen("4k3/8/8/8/8/1p1p4/8/2N1K3 w - - 0 1").unwrap();
+
+        let params = EvalParams::DEFAULT;
+        let knight_safe = mobility_for_color(&params, &safe, Color::White);
+        let knight_covered = mobility_for_color(&params, &covered, Color::White);
+        assert_eq!(
+            knight_safe - knight_covered,
+            params.mobility[Piece::Knight as usize] * 2
+        );
+    }
+}
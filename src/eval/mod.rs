@@ -0,0 +1,323 @@
+use crate::chess::{Board, Color, GameResult, Movement, Piece, NUM_PIECES};
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+mod king_safety;
+mod mobility;
+mod nnue;
+mod params;
+mod pawns;
+mod trace;
+
+pub use nnue::{Accumulator, Network};
+pub use params::EvalParams;
+pub use trace::{trace, trace_with_params, EvalTrace, Term};
+
+// https://www.chessprogramming.org/Tapered_Eval
+
+// Every term has a midgame and an endgame value, the final score is
+// interpolated between the two by how much material is left (the phase).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Tapered {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Tapered {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Tapered { mg, eg }
+    }
+
+    // Interpolate, phase goes from MAX_PHASE (opening) down to 0 (bare kings)
+    pub fn taper(&self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Tapered {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Tapered::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl Sub for Tapered {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Tapered::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Tapered {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Tapered::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Tapered {
+    type Output = Self;
+    fn mul(self, n: i32) -> Self {
+        Tapered::new(self.mg * n, self.eg * n)
+    }
+}
+
+impl AddAssign for Tapered {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Tapered {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+// Phase contributed by each piece, pawns and kings don't count.
+const PHASE_WEIGHTS: [i32; NUM_PIECES] = [0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+// Not i16::MAX, because we use i16::MAX as infinity, ie.
+// we want best move updated from None -> Some(mv) even if
+// the best move still results in our demise.
+pub const MATE: i16 = 10000;
+
+// Move ordering doesn't need tuned values
+#[inline]
+fn get_piece_value(piece: Piece) -> i16 {
+    EvalParams::DEFAULT.piece_values[piece as usize].mg as i16
+}
+
+// Material plus piece square tables for one piece type,
+// tables are from white's perspective so black's squares are flipped.
+#[inline]
+fn get_piece_score(params: &EvalParams, board: &Board, color: Color, piece: Piece) -> Tapered {
+    let value = params.piece_values[piece as usize];
+    let table = &params.psqt[piece as usize];
+
+    let bitboard = *board.pieces(piece) & *board.color_combined(color);
+
+    let mut score = Tapered::default();
+    for sq in bitboard {
+        let i = sq.flip_vertical_if(color == Color::Black).0 as usize;
+        score += value + table[i];
+    }
+    score
+}
+
+#[inline]
+fn get_piece_score_for_color(params: &EvalParams, board: &Board, color: Color) -> Tapered {
+    let mut score = Tapered::default();
+    score += get_piece_score(params, board, color, Piece::Pawn);
+    score += get_piece_score(params, board, color, Piece::Knight);
+    score += get_piece_score(params, board, color, Piece::Bishop);
+    score += get_piece_score(params, board, color, Piece::Rook);
+    score += get_piece_score(params, board, color, Piece::Queen);
+    score += get_piece_score(params, board, color, Piece::King);
+    score
+}
+
+// Game phase from the remaining material, MAX_PHASE at the start
+// (or more, with promotions) and 0 when only kings and pawns are left.
+pub fn get_phase(board: &Board) -> i32 {
+    let phase: i32 = (0..NUM_PIECES)
+        .map(|p| board.pieces[p].count_ones() as i32 * PHASE_WEIGHTS[p])
+        .sum();
+
+    i32::min(phase, MAX_PHASE)
+}
+
+// Score of a position that isn't over, from white's perspective
+pub fn evaluate(params: &EvalParams, board: &Board) -> i16 {
+    let mut score = Tapered::default();
+
+    score += get_piece_score_for_color(params, board, Color::White);
+    score -= get_piece_score_for_color(params, board, Color::Black);
+    score += pawns::evaluate(params, board);
+    score += mobility::evaluate(params, board);
+    score += king_safety::evaluate(params, board);
+    score += params.tempo * board.side_to_move.polarize() as i32;
+
+    score.taper(get_phase(board)) as i16
+}
+
+pub fn get_score_ongoing(board: &Board) -> i16 {
+    evaluate(&EvalParams::DEFAULT, board)
+}
+
+pub fn get_score(board: &Board, game_over: bool) -> i16 {
+    // NOTE: Make sure eval is never more then MATE when it is checkmate,
+    // Otherwise the engine will delay mate to capture pieces.
+    if game_over {
+        match board.game_result() {
+            GameResult::Win => MATE * board.side_to_move.polarize(),
+            GameResult::Loss => MATE * board.side_to_move.other().polarize(),
+            GameResult::Draw => 0,
+        }
+    } else {
+        get_score_ongoing(board)
+    }
+}
+
+// How promising is the move, on the board? this returns
+// higher for more promise, lower for less. (relative to maker of mv)
+pub fn get_promise(board: &Board, mv: &Movement) -> i16 {
+    // let after_move = board.make_move(mv);
+    let mut p = 0; // board.side_to_move.polarize() * get_score_ongoing(&after_move);
+
+    // Drops don't capture, so they don't need the piece
+    let moved_piece = match board.piece_on(mv.from_square) {
+        Some(piece) => piece,
+        None => return p,
+    };
+
+    // most valuable victim, least valuable aggressor
+    if let Some(captured) = mv.captured_piece() {
+        p += get_piece_value(captured);
+        p -= get_piece_value(moved_piece) / 100;
+    }
+
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::STARTING_FEN;
+    use crate::movegen::MoveGen;
+
+    #[test]
+    fn test_get_promise() {
+        let b =
+            Board::from_fen("r1b2rk1/ppppnppp/2n2q2/2b1P3/3N4/2P1B3/PP3PPP/RN1QKB1R w KQ - 1 2")
+                .unwrap();
+        let d4c4 = b.parse_move("d4c6").unwrap();
+        let f1e2 = b.parse_move("f1e2").unwrap();
+        assert!(get_promise(&b, &d4c4) > get_promise(&b, &f1e2));
+
+        let pawn_takes_queen = b.parse_move("e5f6").unwrap();
+        assert!(get_promise(&b, &pawn_takes_queen) > get_promise(&b, &d4c4));
+    }
+
+    #[test]
+    fn test_get_score_e2e4() {
+        let mut b = Board::from_start_pos();
+        b.make_move_mut(&b.parse_move("e2e4").expect("e2e4 move is valid"));
+
+        let score = get_score(&b, MoveGen::new_legal(&b).count() == 0);
+        eprintln!("score: {}", score);
+        assert!(score > 0); // White should have the advantage
+    }
+
+    #[test]
+    fn test_get_score_mate_for_black() {
+        let b = Board::from_fen("r1b1kb1r/pppp1pp1/2n5/1B2p3/4PP2/6p1/PPPP2Pq/RNBQNRK1 w kq - 0 8")
+            .unwrap();
+        let score = get_score(&b, MoveGen::new_legal(&b).count() == 0);
+
+        eprintln!("board:\n{}", b);
+        eprintln!("score (white in checkmate) = {}", score);
+        assert_eq!(score, -MATE);
+    }
+
+    #[test]
+    fn test_get_score_mate_for_white() {
+        let b = Board::from_fen("k1R5/8/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+        let score = get_score(&b, MoveGen::new_legal(&b).count() == 0);
+
+        eprintln!("board:\n{}", b);
+        eprintln!("score (black in checkmate) = {}", score);
+        assert_eq!(score, MATE);
+    }
+
+    #[test]
+    fn test_phase() {
+        assert_eq!(get_phase(&Board::from_start_pos()), MAX_PHASE);
+        assert_eq!(
+            get_phase(&Board::from_fen("4k3/pppp4/8/8/8/8/4PPPP/4K3 w - - 0 1").unwrap()),
+            0
+        );
+        assert_eq!(
+            get_phase(&Board::from_fen("3rk3/8/8/8/8/8/8/2N1K3 w - - 0 1").unwrap()),
+            3
+        );
+    }
+
+    #[test]
+    fn test_king_centralized_endgame() {
+        let center = Board::from_fen("8/8/4k3/8/3K4/8/4P3/8 w - - 0 1").unwrap();
+        let corner = Board::from_fen("8/8/4k3/8/8/8/4P3/K7 w - - 0 1").unwrap();
+        assert!(get_score_ongoing(&center) > get_score_ongoing(&corner));
+    }
+
+    #[test]
+    fn test_king_hides_midgame() {
+        let castled = Board::from_fen(
+            "r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQ1RK1 w - - 0 1",
+        )
+        .unwrap();
+        let center = Board::from_fen(
+            "r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2NK1N2/PPPP1PPP/R1BQ1R2 w - - 0 1",
+        )
+        .unwrap();
+        assert!(get_score_ongoing(&castled) > get_score_ongoing(&center));
+    }
+
+    #[test]
+    fn test_eval_mirror_symmetric() {
+        let fens = [
+            STARTING_FEN,
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "8/8/4Nk2/3KN3/3N4/8/8/8 b - - 4 3",
+        ];
+
+        for fen in fens.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(
+                get_score_ongoing(&board),
+                -get_score_ongoing(&board.mirror()),
+                "asymmetric eval for {}",
+                fen
+            );
+        }
+    }
+
+    macro_rules! test_move_delta {
+        (name: $name:ident, fen: $fen:expr, mv: $mv:expr, assert: $assert:expr,) => {
+            #[test]
+            #[allow(clippy::redundant_closure_call)]
+            fn $name() {
+                let mut b = Board::from_fen($fen).unwrap();
+                let mv = b.parse_move($mv).unwrap();
+                let score_before = get_score(&b, MoveGen::new_legal(&b).count() == 0);
+                b.make_move_mut(&mv);
+                eprintln!("\n---- AFTER -----");
+                let score_after = get_score(&b, MoveGen::new_legal(&b).count() == 0);
+                let delta = score_after - score_before;
+                assert!(
+                    $assert(score_before, score_after, delta),
+                    "bad move delta {} for {}",
+                    delta,
+                    mv
+                );
+            }
+        };
+    }
+
+    test_move_delta!(
+        name: test_reasonable_d2d4_value_delta,
+        fen: "r1bqkbnr/pppp1ppp/2n5/4N3/4P3/8/PPPP1PPP/RNBQKB1R b KQkq - 0 1",
+        mv: "d2d4",
+        assert: (|_,_,delta| delta < 200),
+    );
+
+    test_move_delta!(
+        name: test_get_score_castle,
+        fen: "rnbqkb1r/ppp2ppp/3p1n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 4",
+        mv: "e1g1",
+        assert: (|before,after,_| after > before),
+    );
+}
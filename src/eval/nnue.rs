@@ -0,0 +1,394 @@
+use crate::chess::{Board, Color, MoveKind, Movement, Piece, Square, NUM_COLORS, NUM_PIECES};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Efficiently updatable neural network, an alternative to the hand crafted eval.
+// https://www.chessprogramming.org/NNUE
+//
+// 768 inputs (color, piece, square) -> HIDDEN neurons, once from each side's
+// point of view -> clipped relu -> 1 output. The first layer (the accumulator)
+// only changes by a few rows per move, so we update it instead of recomputing it.
+// Weights are i16 but the accumulator and output are summed wider, any weights
+// a file can hold have to work without overflowing.
+//
+// There's no network built in, we don't have a trained one worth embedding.
+// One has to be loaded with the EvalFile option.
+//
+// Weight file layout, all little endian:
+//   b"YNNU", version u32, hidden size u32,
+//   feature weights i16 [768][HIDDEN], feature bias i16 [HIDDEN],
+//   output weights i16 [2][HIDDEN] (side to move first), output bias i16
+
+pub const NUM_FEATURES: usize = NUM_COLORS * NUM_PIECES * 64;
+pub const HIDDEN: usize = 128;
+
+const MAGIC: &[u8; 4] = b"YNNU";
+const VERSION: u32 = 1;
+
+// Quantization, activations are clipped to [0, QA], output weights are scaled
+// by QB and the output to centipawns by SCALE.
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Network {
+    feature_weights: Vec<[i16; HIDDEN]>,
+    feature_bias: [i16; HIDDEN],
+    output_weights: [[i16; HIDDEN]; 2],
+    output_bias: i16,
+}
+
+// First layer output, from both perspectives (indexed by color)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accumulator {
+    values: [[i32; HIDDEN]; NUM_COLORS],
+}
+
+// A piece seen from one side, our pieces come first and black
+// sees the board flipped, so both sides share the same weights.
+#[inline]
+fn feature(perspective: Color, piece: Piece, color: Color, sq: Square) -> usize {
+    let theirs = (color != perspective) as usize;
+    let sq = sq.flip_vertical_if(perspective == Color::Black);
+    theirs * NUM_PIECES * 64 + piece as usize * 64 + sq.0 as usize
+}
+
+type Feature = (Piece, Color, Square);
+
+// Pieces a move adds and removes, at most two of each (castling, captures).
+#[derive(Default)]
+struct Delta {
+    added: [Option<Feature>; 2],
+    removed: [Option<Feature>; 2],
+}
+
+fn push(features: &mut [Option<Feature>; 2], feature: Feature) {
+    let slot = features
+        .iter_mut()
+        .find(|f| f.is_none())
+        .expect("delta full");
+    *slot = Some(feature);
+}
+
+fn move_delta(board: &Board, mv: &Movement) -> Delta {
+    let mut delta = Delta::default();
+    let color = board.side_to_move;
+
+    // Nothing moves, the piece just shows up
+    if let MoveKind::Drop(piece) = mv.kind {
+        push(&mut delta.added, (piece, color, mv.to_square));
+        return delta;
+    }
+
+    let piece = board.piece_on(mv.from_square).expect("no piece on square");
+
+    match mv.kind {
+        MoveKind::Capture(captured) => {
+            push(&mut delta.removed, (captured, color.other(), mv.to_square));
+        }
+
+        MoveKind::Castle(castling) => {
+            // King takes rook, so the move squares aren't where the pieces end up
+            push(&mut delta.removed, (Piece::King, color, mv.from_square));
+            push(&mut delta.removed, (Piece::Rook, color, mv.to_square));
+            push(
+                &mut delta.added,
+                (Piece::King, color, castling.king_destination()),
+            );
+            push(
+                &mut delta.added,
+                (Piece::Rook, color, castling.rook_destination()),
+            );
+            return delta;
+        }
+
+        MoveKind::EnPassant => {
+            let captured = match color {
+                Color::White => mv.to_square.down(1),
+                Color::Black => mv.to_square.up(1),
+            };
+            push(
+                &mut delta.removed,
+                (Piece::Pawn, color.other(), captured.unwrap()),
+            );
+        }
+
+        _ => {}
+    }
+
+    push(&mut delta.removed, (piece, color, mv.from_square));
+    push(
+        &mut delta.added,
+        (mv.promote.unwrap_or(piece), color, mv.to_square),
+    );
+
+    delta
+}
+
+impl Network {
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Network> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        if bytes.len() < 12 || &bytes[0..4] != MAGIC {
+            return Err(invalid("not a yobmef network file"));
+        }
+
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if u32_at(4) != VERSION {
+            return Err(invalid("unsupported network version"));
+        }
+        if u32_at(8) as usize != HIDDEN {
+            return Err(invalid("network has the wrong hidden layer size"));
+        }
+
+        let body = &bytes[12..];
+        let want = 2 * (NUM_FEATURES * HIDDEN + HIDDEN + 2 * HIDDEN + 1);
+        if body.len() != want {
+            return Err(invalid("network file has the wrong size"));
+        }
+
+        let mut values = body
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]));
+        let mut row = || {
+            let mut row = [0; HIDDEN];
+            row.iter_mut().for_each(|v| *v = values.next().unwrap());
+            row
+        };
+
+        let feature_weights = (0..NUM_FEATURES).map(|_| row()).collect();
+        let feature_bias = row();
+        let output_weights = [row(), row()];
+        let output_bias = values.next().unwrap();
+
+        Ok(Network {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(HIDDEN as u32).to_le_bytes());
+
+        let rows = self
+            .feature_weights
+            .iter()
+            .chain(std::iter::once(&self.feature_bias))
+            .chain(self.output_weights.iter());
+        for row in rows {
+            row.iter()
+                .for_each(|v| bytes.extend_from_slice(&v.to_le_bytes()));
+        }
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+
+        bytes
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Network> {
+        Network::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    // Small random weights, so tests have something to chew on.
+    #[cfg(test)]
+    pub(crate) fn random(seed: u64) -> Network {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut row = || {
+            let mut row = [0; HIDDEN];
+            row.iter_mut().for_each(|v| *v = rng.gen_range(-64..64));
+            row
+        };
+
+        Network {
+            feature_weights: (0..NUM_FEATURES).map(|_| row()).collect(),
+            feature_bias: row(),
+            output_weights: [row(), row()],
+            output_bias: 0,
+        }
+    }
+
+    fn add(&self, acc: &mut Accumulator, (piece, color, sq): Feature) {
+        for &perspective in [Color::White, Color::Black].iter() {
+            let weights = &self.feature_weights[feature(perspective, piece, color, sq)];
+            let values = &mut acc.values[perspective as usize];
+            for (v, w) in values.iter_mut().zip(weights.iter()) {
+                *v += *w as i32;
+            }
+        }
+    }
+
+    fn sub(&self, acc: &mut Accumulator, (piece, color, sq): Feature) {
+        for &perspective in [Color::White, Color::Black].iter() {
+            let weights = &self.feature_weights[feature(perspective, piece, color, sq)];
+            let values = &mut acc.values[perspective as usize];
+            for (v, w) in values.iter_mut().zip(weights.iter()) {
+                *v -= *w as i32;
+            }
+        }
+    }
+
+    // Accumulator from scratch
+    pub fn refresh(&self, board: &Board) -> Accumulator {
+        let bias = self.feature_bias.map(|b| b as i32);
+        let mut acc = Accumulator {
+            values: [bias; NUM_COLORS],
+        };
+
+        for &color in [Color::White, Color::Black].iter() {
+            for piece in 0..NUM_PIECES {
+                let bitboard = board.pieces[piece] & *board.color_combined(color);
+                for sq in bitboard {
+                    self.add(&mut acc, (Piece::from_usize(piece).unwrap(), color, sq));
+                }
+            }
+        }
+
+        acc
+    }
+
+    // Accumulator after mv is made on board, given the accumulator for board.
+    pub fn update(&self, acc: &Accumulator, board: &Board, mv: &Movement) -> Accumulator {
+        let delta = move_delta(board, mv);
+        let mut acc = acc.clone();
+
+        delta
+            .removed
+            .iter()
+            .flatten()
+            .for_each(|&f| self.sub(&mut acc, f));
+        delta
+            .added
+            .iter()
+            .flatten()
+            .for_each(|&f| self.add(&mut acc, f));
+
+        acc
+    }
+
+    // Score in centipawns, relative to the side to move.
+    pub fn evaluate(&self, acc: &Accumulator, side_to_move: Color) -> i16 {
+        let ours = &acc.values[side_to_move as usize];
+        let theirs = &acc.values[side_to_move.other() as usize];
+
+        // Big weights can take this past i32
+        let mut output: i64 = 0;
+        for (values, weights) in [ours, theirs].iter().zip(self.output_weights.iter()) {
+            for (&v, &w) in values.iter().zip(weights.iter()) {
+                output += (v.clamp(0, QA) * w as i32) as i64;
+            }
+        }
+
+        let score =
+            (output + (self.output_bias as i32 * QA) as i64) * SCALE as i64 / (QA * QB) as i64;
+        score.clamp(-super::MATE as i64 / 2, super::MATE as i64 / 2) as i16
+    }
+
+    pub fn evaluate_board(&self, board: &Board) -> i16 {
+        self.evaluate(&self.refresh(board), board.side_to_move)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::MoveGen;
+
+    const FENS: [&str; 4] = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "8/2p5/3p4/KP5r/1R2Pp1k/8/6P1/8 b - e3 0 1",
+    ];
+
+    #[test]
+    fn test_incremental_matches_refresh() {
+        let net = Network::random(1);
+
+        for fen in FENS.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            let acc = net.refresh(&board);
+
+            // Two plies deep, covers castling, promotions and en passant from these
+            for mv in MoveGen::new_legal(&board) {
+                let child = board.make_move(&mv);
+                let child_acc = net.update(&acc, &board, &mv);
+                assert_eq!(child_acc, net.refresh(&child), "{} after {}", fen, mv);
+
+                for reply in MoveGen::new_legal(&child) {
+                    let grandchild = child.make_move(&reply);
+                    assert_eq!(
+                        net.update(&child_acc, &child, &reply),
+                        net.refresh(&grandchild),
+                        "{} after {} {}",
+                        fen,
+                        mv,
+                        reply
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_symmetric() {
+        let net = Network::random(2);
+        for fen in FENS.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(
+                net.evaluate_board(&board),
+                net.evaluate_board(&board.mirror()),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    // The biggest weights a file can hold, the sums must not overflow
+    #[test]
+    fn test_large_weights() {
+        for &w in [i16::MAX, i16::MIN].iter() {
+            let net = Network {
+                feature_weights: vec![[w; HIDDEN]; NUM_FEATURES],
+                feature_bias: [w; HIDDEN],
+                output_weights: [[w; HIDDEN]; 2],
+                output_bias: w,
+            };
+            let net = Network::from_bytes(&net.to_bytes()).unwrap();
+
+            for fen in FENS.iter() {
+                let board = Board::from_fen(fen).unwrap();
+                let acc = net.refresh(&board);
+                let score = net.evaluate(&acc, board.side_to_move);
+                assert!(score.abs() <= crate::eval::MATE / 2, "{}", fen);
+
+                for mv in MoveGen::new_legal(&board) {
+                    let child = board.make_move(&mv);
+                    assert_eq!(net.update(&acc, &board, &mv), net.refresh(&child));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let net = Network::random(3);
+        let bytes = net.to_bytes();
+        assert!(Network::from_bytes(&bytes).unwrap() == net);
+
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+        assert!(Network::from_bytes(b"not a network").is_err());
+    }
+}
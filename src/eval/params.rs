@@ -0,0 +1,559 @@
+use super::Tapered;
+use crate::chess::{Piece, NUM_PIECES};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+// Every weight the evaluation uses. The defaults are hand picked, a tuned
+// set can be saved to and loaded from a text file with one line per field,
+// the field name followed by its values (midgame then endgame for tapered
+// values, in square order for tables).
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalParams {
+    pub piece_values: [Tapered; NUM_PIECES],
+    pub psqt: [[Tapered; 64]; NUM_PIECES], // From white's perspective, a1 first
+    pub tempo: Tapered,                    // Side to move gets inherent advantage
+
+    // Pawn structure
+    pub doubled: Tapered,
+    pub isolated: Tapered,
+    pub backward: Tapered,
+    pub connected: [Tapered; 8], // By rank, relative to the pawn's color
+    pub passed: [Tapered; 8],
+    pub passed_free_path: [Tapered; 8], // Nothing in front of the passed pawn
+    pub passed_king_support: [Tapered; 8], // Our king next to the square in front of it
+
+    // Per safe square a piece can move to, relative to a baseline
+    pub mobility: [Tapered; NUM_PIECES],
+
+    // King safety
+    pub shield_close: Tapered, // Per pawn right in front of the king
+    pub shield_far: Tapered,   // Per pawn two squares in front
+    pub semi_open_file: Tapered,
+    pub open_file: Tapered,
+    pub attacker_weight: [i32; NUM_PIECES], // Attack units per king zone square hit
+    pub king_attack: [Tapered; 64],         // Penalty by attack units
+}
+
+// Anything the params are made of, flattened to a list of integers
+// so we can read, write and tune them without caring about the shape.
+pub trait Param {
+    fn flatten(&self, out: &mut Vec<i32>);
+    fn unflatten(&mut self, values: &mut dyn Iterator<Item = i32>) -> Option<()>;
+}
+
+impl Param for i32 {
+    fn flatten(&self, out: &mut Vec<i32>) {
+        out.push(*self);
+    }
+
+    fn unflatten(&mut self, values: &mut dyn Iterator<Item = i32>) -> Option<()> {
+        *self = values.next()?;
+        Some(())
+    }
+}
+
+impl Param for Tapered {
+    fn flatten(&self, out: &mut Vec<i32>) {
+        out.push(self.mg);
+        out.push(self.eg);
+    }
+
+    fn unflatten(&mut self, values: &mut dyn Iterator<Item = i32>) -> Option<()> {
+        self.mg = values.next()?;
+        self.eg = values.next()?;
+        Some(())
+    }
+}
+
+impl<T: Param, const N: usize> Param for [T; N] {
+    fn flatten(&self, out: &mut Vec<i32>) {
+        self.iter().for_each(|p| p.flatten(out));
+    }
+
+    fn unflatten(&mut self, values: &mut dyn Iterator<Item = i32>) -> Option<()> {
+        for p in self.iter_mut() {
+            p.unflatten(values)?;
+        }
+        Some(())
+    }
+}
+
+// List the fields once, so adding a parameter can't break loading and saving.
+macro_rules! params {
+    ($($field:ident),* $(,)?) => {
+        impl EvalParams {
+            fn fields(&self) -> Vec<(&'static str, &dyn Param)> {
+                vec![$((stringify!($field), &self.$field as &dyn Param)),*]
+            }
+
+            fn fields_mut(&mut self) -> Vec<(&'static str, &mut dyn Param)> {
+                vec![$((stringify!($field), &mut self.$field as &mut dyn Param)),*]
+            }
+        }
+    };
+}
+
+params!(
+    piece_values,
+    psqt,
+    tempo,
+    doubled,
+    isolated,
+    backward,
+    connected,
+    passed,
+    passed_free_path,
+    passed_king_support,
+    mobility,
+    shield_close,
+    shield_far,
+    semi_open_file,
+    open_file,
+    attacker_weight,
+    king_attack,
+);
+
+impl EvalParams {
+    pub const DEFAULT: EvalParams = EvalParams {
+        piece_values: [
+            Tapered::new(100, 120),
+            Tapered::new(320, 300),
+            Tapered::new(330, 320),
+            Tapered::new(500, 540),
+            Tapered::new(975, 960),
+            Tapered::new(0, 0),
+        ],
+        psqt: [
+            psqt(PAWN_MG_TABLE, PAWN_EG_TABLE),
+            psqt(KNIGHT_MG_TABLE, KNIGHT_EG_TABLE),
+            psqt(BISHOP_MG_TABLE, BISHOP_EG_TABLE),
+            psqt(ROOK_MG_TABLE, ROOK_EG_TABLE),
+            psqt(QUEEN_MG_TABLE, QUEEN_EG_TABLE),
+            psqt(KING_MG_TABLE, KING_EG_TABLE),
+        ],
+        tempo: Tapered::new(10, 5),
+
+        doubled: Tapered::new(-10, -20),
+        isolated: Tapered::new(-10, -15),
+        backward: Tapered::new(-8, -10),
+        connected: by_rank([0, 5, 7, 10, 15, 25, 40, 0], [0, 3, 5, 8, 12, 20, 30, 0]),
+        passed: by_rank(
+            [0, 5, 10, 15, 25, 40, 60, 0],
+            [0, 10, 15, 25, 45, 75, 120, 0],
+        ),
+        passed_free_path: by_rank([0, 0, 0, 0, 5, 10, 15, 0], [0, 0, 5, 10, 20, 35, 60, 0]),
+        passed_king_support: by_rank([0; 8], [0, 0, 5, 10, 15, 20, 25, 0]),
+
+        mobility: [
+            Tapered::new(0, 0),
+            Tapered::new(4, 4),
+            Tapered::new(5, 5),
+            Tapered::new(2, 4),
+            Tapered::new(1, 2),
+            Tapered::new(0, 0),
+        ],
+
+        shield_close: Tapered::new(12, 0),
+        shield_far: Tapered::new(6, 0),
+        semi_open_file: Tapered::new(-12, 0),
+        open_file: Tapered::new(-25, 0),
+        attacker_weight: [0, 2, 2, 3, 5, 0],
+        king_attack: king_attack(KING_ATTACK_TABLE),
+    };
+
+    // Every value, in the same order as the file format.
+    pub fn to_vec(&self) -> Vec<i32> {
+        let mut out = Vec::new();
+        for (_, field) in self.fields() {
+            field.flatten(&mut out);
+        }
+        out
+    }
+
+    // Which of the to_vec values the evaluation of a standard chess position
+    // ever reads. The rest can't change the score, so tuning them is wasted time.
+    pub fn tunable() -> Vec<bool> {
+        let mut live = EvalParams::DEFAULT;
+        live.set_from_slice(&vec![1; live.to_vec().len()]);
+
+        // Both sides always have one king, so its value cancels out
+        live.piece_values[Piece::King as usize] = Tapered::default();
+
+        // Only knights, bishops, rooks and queens are counted
+        for &piece in [Piece::Pawn, Piece::King].iter() {
+            live.mobility[piece as usize] = Tapered::default();
+            live.attacker_weight[piece as usize] = 0;
+        }
+
+        // No pawns on the back ranks
+        for rank in [0, 7].iter().copied() {
+            for file in 0..8 {
+                live.psqt[Piece::Pawn as usize][rank * 8 + file] = Tapered::default();
+            }
+            live.connected[rank] = Tapered::default();
+            live.passed[rank] = Tapered::default();
+            live.passed_free_path[rank] = Tapered::default();
+            live.passed_king_support[rank] = Tapered::default();
+        }
+
+        live.to_vec().iter().map(|&v| v != 0).collect()
+    }
+
+    // Inverse of to_vec, panics if values has the wrong length.
+    pub fn set_from_slice(&mut self, values: &[i32]) {
+        let mut values = values.iter().copied();
+        for (name, field) in self.fields_mut() {
+            field
+                .unflatten(&mut values)
+                .unwrap_or_else(|| panic!("not enough values for {}", name));
+        }
+        assert!(values.next().is_none(), "too many values");
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<EvalParams> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# yobmef eval params")?;
+
+        for (name, field) in self.fields() {
+            let mut values = Vec::new();
+            field.flatten(&mut values);
+
+            write!(f, "{}", name)?;
+            for v in values {
+                write!(f, " {}", v)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Fields missing from the file keep their default value,
+// so old files still load after we add a parameter.
+impl FromStr for EvalParams {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = EvalParams::DEFAULT;
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap();
+            let values = words
+                .map(i32::from_str)
+                .collect::<Result<Vec<i32>, _>>()
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+            let mut fields = params.fields_mut();
+            let field = match fields.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, field)) => field,
+                None => return Err(format!("line {}: unknown parameter {}", i + 1, name)),
+            };
+
+            let mut want = Vec::new();
+            field.flatten(&mut want);
+            if want.len() != values.len() {
+                return Err(format!(
+                    "line {}: {} wants {} values, got {}",
+                    i + 1,
+                    name,
+                    want.len(),
+                    values.len()
+                ));
+            }
+            field.unflatten(&mut values.into_iter()).unwrap();
+        }
+
+        Ok(params)
+    }
+}
+
+const fn psqt(mg: [i16; 64], eg: [i16; 64]) -> [Tapered; 64] {
+    let mut table = [Tapered::new(0, 0); 64];
+    let mut i = 0;
+    while i < 64 {
+        table[i] = Tapered::new(mg[i] as i32, eg[i] as i32);
+        i += 1;
+    }
+    table
+}
+
+const fn by_rank(mg: [i32; 8], eg: [i32; 8]) -> [Tapered; 8] {
+    let mut table = [Tapered::new(0, 0); 8];
+    let mut i = 0;
+    while i < 8 {
+        table[i] = Tapered::new(mg[i], eg[i]);
+        i += 1;
+    }
+    table
+}
+
+// King attacks matter a lot less once the queens are off
+const fn king_attack(mg: [i32; 64]) -> [Tapered; 64] {
+    let mut table = [Tapered::new(0, 0); 64];
+    let mut i = 0;
+    while i < 64 {
+        table[i] = Tapered::new(-mg[i], -mg[i] / 4);
+        i += 1;
+    }
+    table
+}
+
+// Default tables, hand picked with inspiration from:
+// https://www.chess.com/article/view/the-evaluation-of-material-imbalances-by-im-larry-kaufman
+// https://www.chessprogramming.org/Simplified_Evaluation_Function
+
+#[rustfmt::skip]
+const PAWN_MG_TABLE: [i16; 64] = [
+    0,  0,  0,   0,   0,   0,  0,  0,
+    5,  10, 10, -20, -20,  10, 10, 5,
+    5, -5, -10,  0,   0,  -10, -5,  5,
+    0,  0,  0,   20,  20,  0,   0,  0,
+    5,  5,  10,  25,  25,  10,  5,  5,
+    10, 10, 20,  30,  30,  20,  10, 10,
+    50, 50, 50,  50,  50,  50,  50, 50,
+    0,  0,  0,   0,   0,   0,   0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_MG_TABLE: [i16; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,  0,   5,   5,   0,  -20, -40,
+    -30,  5,   10,  15,  15,  10,  5,  -30,
+    -30,  0,   15,  20,  20,  15,  0,  -30,
+    -30,  5,   15,  20,  20,  15,  5,  -30,
+    -30,  0,   10,  15,  15,  10,  0,  -30,
+    -40, -20,  0,   0,   0,   0,  -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_MG_TABLE: [i16; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,  5,   0,   0,   0,   0,   5,  -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,  0,   10,  10,  10,  10,  0,  -10,
+    -10,  5,   5,   10,  10,  5,   5,  -10,
+    -10,  0,   5,   10,  10,  5,   0,  -10,
+    -10,  0,   0,   0,   0,   0,   0,  -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_MG_TABLE: [i16; 64] = [
+    -5,    0,   0,   5,  5,   0,   0,  -5,
+    -5,    0,   0,   0,  0,   0,   0,  -5,
+    -5,    0,   0,   0,  0,   0,   0,  -5,
+    -5,    0,   0,   0,  0,   0,   0,  -5,
+    -5,    0,   0,   0,  0,   0,   0,  -5,
+    -5,    0,   0,   0,  0,   0,   0,  -5,
+     5,    10,  10,  10, 10,  10,  10,  5,
+     0,    0,   0,   0,  0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN_MG_TABLE: [i16; 64] = [
+    -20, -10, -10, -5, -5, -10, -10, -20,
+    -10,  0,   5,   0,  0,  0,   0,  -10,
+    -10,  5,   5,   5,  5,  5,   0,  -10,
+     0,   0,   5,   5,  5,  5,   0,  -5,
+    -5,   0,   5,   5,  5,  5,   0,  -5,
+    -10,  0,   5,   5,  5,  5,   0,  -10,
+    -10,  0,   0,   0,  0,  0,   0,  -10,
+    -20, -10, -10, -5, -5, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const KING_MG_TABLE: [i16; 64] = [
+     20,  50,  40,    0,   0,  10,  50,   20,
+     20,  20,   0,    0,   0,   0,  20,   20,
+    -10, -20, -20,  -20, -20, -20, -20,  -10,
+    -20, -30, -30,  -40, -40, -30, -30,  -20,
+    -30, -40, -40,  -50, -50, -40, -40,  -30,
+    -30, -40, -40,  -50, -50, -40, -40,  -30,
+    -30, -40, -40,  -50, -50, -40, -40,  -30,
+    -30, -40, -40,  -50, -50, -40, -40,  -30,
+];
+
+// Endgame tables, pawns want to run, pieces and especially the king
+// want to be in the center.
+
+#[rustfmt::skip]
+const PAWN_EG_TABLE: [i16; 64] = [
+    0,   0,   0,   0,   0,   0,   0,   0,
+    0,   0,   0,   0,   0,   0,   0,   0,
+    5,   5,   5,   5,   5,   5,   5,   5,
+    15,  15,  15,  15,  15,  15,  15,  15,
+    30,  30,  30,  30,  30,  30,  30,  30,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    80,  80,  80,  80,  80,  80,  80,  80,
+    0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_EG_TABLE: [i16; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20, -5,   0,   0,  -5,  -20, -40,
+    -30, -5,   5,   10,  10,  5,  -5,  -30,
+    -30,  0,   10,  15,  15,  10,  0,  -30,
+    -30,  0,   10,  15,  15,  10,  0,  -30,
+    -30, -5,   5,   10,  10,  5,  -5,  -30,
+    -40, -20, -5,   0,   0,  -5,  -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_EG_TABLE: [i16; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,  0,   0,   0,   0,   0,   0,  -10,
+    -10,  0,   5,   5,   5,   5,   0,  -10,
+    -10,  0,   5,   10,  10,  5,   0,  -10,
+    -10,  0,   5,   10,  10,  5,   0,  -10,
+    -10,  0,   5,   5,   5,   5,   0,  -10,
+    -10,  0,   0,   0,   0,   0,   0,  -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_EG_TABLE: [i16; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     10,  10,  10,  10,  10,  10,  10,  10,
+     5,   5,   5,   5,   5,   5,   5,   5,
+];
+
+#[rustfmt::skip]
+const QUEEN_EG_TABLE: [i16; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,  0,   0,   0,   0,   0,   0,  -10,
+    -10,  0,   10,  10,  10,  10,  0,  -10,
+    -10,  0,   10,  15,  15,  10,  0,  -10,
+    -10,  0,   10,  15,  15,  10,  0,  -10,
+    -10,  0,   10,  10,  10,  10,  0,  -10,
+    -10,  0,   0,   0,   0,   0,   0,  -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const KING_EG_TABLE: [i16; 64] = [
+    -50, -30, -30, -30, -30, -30, -30, -50,
+    -30, -20,  0,   0,   0,   0,  -20, -30,
+    -30,  0,   20,  25,  25,  20,  0,  -30,
+    -30,  0,   25,  40,  40,  25,  0,  -30,
+    -30,  0,   25,  40,  40,  25,  0,  -30,
+    -30,  0,   20,  25,  25,  20,  0,  -30,
+    -30, -20,  0,   0,   0,   0,  -20, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+// https://www.chessprogramming.org/King_Safety
+#[rustfmt::skip]
+const KING_ATTACK_TABLE: [i32; 64] = [
+      0,   0,   1,   2,   3,   5,   7,   9,  12,  15,
+     18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
+     68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
+    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+    494, 500, 500, 500,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::Board;
+
+    #[test]
+    fn test_roundtrip_text() {
+        let mut params = EvalParams::DEFAULT;
+        params.tempo = Tapered::new(13, -2);
+        params.psqt[5][63] = Tapered::new(-7, 99);
+        params.attacker_weight[4] = 11;
+
+        let parsed: EvalParams = params.to_string().parse().unwrap();
+        assert_eq!(parsed, params);
+    }
+
+    #[test]
+    fn test_roundtrip_vec() {
+        let mut values = EvalParams::DEFAULT.to_vec();
+        values[3] += 1;
+
+        let mut params = EvalParams::DEFAULT;
+        params.set_from_slice(&values);
+        assert_eq!(params.to_vec(), values);
+        assert_eq!(params.piece_values[1].eg, 301);
+    }
+
+    // Changing what isn't tunable doesn't change any score
+    #[test]
+    fn test_tunable() {
+        let tunable = EvalParams::tunable();
+        let mut values = EvalParams::DEFAULT.to_vec();
+        assert_eq!(tunable.len(), values.len());
+
+        for (v, &t) in values.iter_mut().zip(tunable.iter()) {
+            if !t {
+                *v += 50;
+            }
+        }
+        let mut params = EvalParams::DEFAULT;
+        params.set_from_slice(&values);
+
+        for fen in crate::bench::POSITIONS.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(
+                crate::eval::evaluate(&params, &board),
+                crate::eval::evaluate(&EvalParams::DEFAULT, &board),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_partial_file() {
+        let params: EvalParams = "# just the tempo\ntempo 20 0\n".parse().unwrap();
+        assert_eq!(params.tempo, Tapered::new(20, 0));
+        assert_eq!(params.psqt, EvalParams::DEFAULT.psqt);
+    }
+
+    #[test]
+    fn test_bad_files() {
+        assert!("tempo 1".parse::<EvalParams>().is_err());
+        assert!("tempo 1 2 3".parse::<EvalParams>().is_err());
+        assert!("tempo 1 x".parse::<EvalParams>().is_err());
+        assert!("bogus 1 2".parse::<EvalParams>().is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use super::{EvalParams, Tapered};
+use crate::bitboard::BitBoard;
+use crate::chess::{Board, Color, Piece, Square, NUM_COLORS};
+use crate::movegen;
+use crate::zobrist;
+use std::cell::RefCell;
+
+// Pawn structure evaluation, everything that only depends on where the pawns
+// are is cached in a small table keyed by the pawn hash. We cache the features
+// rather than the score so the same table works for any EvalParams (the tuner
+// changes them all the time). Passed pawns also depend on the other pieces,
+// so they get scored fresh.
+
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+// The pawn structure of one color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Structure {
+    doubled: i32,
+    isolated: i32,
+    backward: i32,
+    connected: BitBoard,
+    passed: BitBoard,
+}
+
+impl Structure {
+    const fn empty() -> Self {
+        Structure {
+            doubled: 0,
+            isolated: 0,
+            backward: 0,
+            connected: BitBoard::empty(),
+            passed: BitBoard::empty(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PawnEntry {
+    key: u64,
+    structure: [Structure; NUM_COLORS],
+}
+
+impl PawnEntry {
+    // NOTE: Boards without pawns hash to 0, which this entry correctly describes.
+    const fn empty() -> Self {
+        PawnEntry {
+            key: 0,
+            structure: [Structure::empty(); NUM_COLORS],
+        }
+    }
+}
+
+thread_local! {
+    static PAWN_TABLE: RefCell<Vec<PawnEntry>> =
+        RefCell::new(vec![PawnEntry::empty(); PAWN_TABLE_SIZE]);
+}
+
+#[inline]
+fn adjacent_files(file: u8) -> BitBoard {
+    let mut mask = BitBoard::empty();
+    if file > 0 {
+        mask |= BitBoard::file_mask(file - 1);
+    }
+    if file < 7 {
+        mask |= BitBoard::file_mask(file + 1);
+    }
+    mask
+}
+
+// Every square strictly in front of the rank, from color's point of view.
+#[inline]
+fn forward_ranks(color: Color, rank: u8) -> BitBoard {
+    match color {
+        Color::White if rank == 7 => BitBoard::empty(),
+        Color::White => BitBoard(!0 << (8 * (rank + 1))),
+        Color::Black => BitBoard(!(!0 << (8 * rank))),
+    }
+}
+
+#[inline]
+fn relative_rank(color: Color, sq: Square) -> usize {
+    sq.flip_vertical_if(color == Color::Black).rank() as usize
+}
+
+// Squares attacked by any of the pawns, all at once. For one pawn
+// use movegen::pawn_attacks.
+pub fn pawn_attacks_setwise(pawns: BitBoard, color: Color) -> BitBoard {
+    const NOT_A_FILE: u64 = !0x0101010101010101;
+    const NOT_H_FILE: u64 = !0x8080808080808080;
+
+    match color {
+        Color::White => ((pawns << 9) & NOT_A_FILE) | ((pawns << 7) & NOT_H_FILE),
+        Color::Black => ((pawns >> 7) & NOT_A_FILE) | ((pawns >> 9) & NOT_H_FILE),
+    }
+}
+
+fn structure(board: &Board, color: Color) -> Structure {
+    let pawns = *board.pieces(Piece::Pawn);
+    let ours = pawns & *board.color_combined(color);
+    let theirs = pawns & *board.color_combined(color.other());
+    let their_attacks = pawn_attacks_setwise(theirs, color.other());
+
+    let mut structure = Structure::empty();
+
+    for sq in ours {
+        let file = BitBoard::file_mask(sq.file());
+        let adjacent = adjacent_files(sq.file());
+        let rank = BitBoard(0xff << (8 * sq.rank()));
+        let in_front = forward_ranks(color, sq.rank());
+
+        let front_span = file & in_front;
+        let stop = match color {
+            Color::White => sq.up(1),
+            Color::Black => sq.down(1),
+        };
+
+        // Only the rearmost pawn is counted, so 2 doubled pawns are penalized once.
+        if (ours & front_span).count_ones() > 0 {
+            structure.doubled += 1;
+        }
+
+        let neighbours = ours & adjacent;
+        if neighbours.count_ones() == 0 {
+            structure.isolated += 1;
+        } else {
+            // None of our neighbours are level or behind, so we can never be
+            // defended by a pawn, and we can't safely advance either.
+            let can_be_supported = (neighbours & !in_front).count_ones() > 0;
+            let stop_attacked = stop.is_some_and(|stop| their_attacks.get(stop));
+            if !can_be_supported && stop_attacked {
+                structure.backward += 1;
+            }
+        }
+
+        let phalanx = neighbours & rank;
+        let supported = movegen::pawn_attacks(sq, color.other()) & ours;
+        if (phalanx | supported).count_ones() > 0 {
+            structure.connected.flip_mut(sq);
+        }
+
+        let blockers = theirs & (file | adjacent) & in_front;
+        if blockers.count_ones() == 0 && (ours & front_span).count_ones() == 0 {
+            structure.passed.flip_mut(sq);
+        }
+    }
+
+    structure
+}
+
+fn probe(board: &Board) -> PawnEntry {
+    let key = zobrist::pawn_hash(board);
+
+    PAWN_TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        let entry = &mut table[(key as usize) % PAWN_TABLE_SIZE];
+
+        if entry.key != key {
+            *entry = PawnEntry {
+                key,
+                structure: [
+                    structure(board, Color::White),
+                    structure(board, Color::Black),
+                ],
+            };
+        }
+
+        *entry
+    })
+}
+
+fn score(params: &EvalParams, board: &Board, color: Color, structure: &Structure) -> Tapered {
+    let mut score = params.doubled * structure.doubled
+        + params.isolated * structure.isolated
+        + params.backward * structure.backward;
+
+    for sq in structure.connected {
+        score += params.connected[relative_rank(color, sq)];
+    }
+
+    // Antichess kings can be captured
+    let king = (*board.pieces(Piece::King) & *board.color_combined(color)).next();
+    let occupied = board.combined();
+
+    for sq in structure.passed {
+        let relative_rank = relative_rank(color, sq);
+        score += params.passed[relative_rank];
+
+        // The rest depends on the other pieces
+        let front_span = BitBoard::file_mask(sq.file()) & forward_ranks(color, sq.rank());
+
+        if (front_span & occupied).count_ones() == 0 {
+            score += params.passed_free_path[relative_rank];
+        }
+
+        let stop = match color {
+            Color::White => sq.up(1),
+            Color::Black => sq.down(1),
+        };
+        if stop
+            .zip(king)
+            .is_some_and(|(stop, king)| stop.distance(king) <= 1)
+        {
+            score += params.passed_king_support[relative_rank];
+        }
+    }
+
+    score
+}
+
+pub fn pawns_for_color(params: &EvalParams, board: &Board, color: Color) -> Tapered {
+    let entry = probe(board);
+    score(params, board, color, &entry.structure[color as usize])
+}
+
+// Pawn structure score from white's perspective
+pub fn evaluate(params: &EvalParams, board: &Board) -> Tapered {
+    let [white, black] = probe(board).structure;
+
+    score(params, board, Color::White, &white) - score(params, board, Color::Black, &black)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    fn sq(s: &str) -> Square {
+        Square::from_notation(s).unwrap()
+    }
+
+    #[test]
+    fn test_passed_pawns() {
+        let b = board("4k3/p7/8/3P1p2/2p5/4P3/8/4K3 w - - 0 1");
+        let white_passed = structure(&b, Color::White).passed;
+        let black_passed = structure(&b, Color::Black).passed;
+
+        assert_eq!(white_passed.collect::<Vec<Square>>(), vec![sq("d5")]);
+        assert_eq!(
+            black_passed.collect::<Vec<Square>>(),
+            vec![sq("c4"), sq("a7")]
+        );
+    }
+
+    #[test]
+    fn test_doubled_isolated() {
+        let healthy = board("4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1");
+        let doubled = board("4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1");
+
+        let healthy = evaluate(&EvalParams::DEFAULT, &healthy);
+        let doubled = evaluate(&EvalParams::DEFAULT, &doubled);
+        assert!(healthy.mg > doubled.mg && healthy.eg > doubled.eg);
+    }
+
+    #[test]
+    fn test_backward() {
+        // d3 can't be supported by c4 or e4, and d4 is attacked by the pawn on e5
+        let b = board("4k3/8/8/4p3/2P1P3/3P4/8/4K3 w - - 0 1");
+        assert_eq!(structure(&b, Color::White).backward, 1);
+
+        // Same structure, but d4 is safe
+        let b = board("4k3/8/4p3/8/2P1P3/3P4/8/4K3 w - - 0 1");
+        assert_eq!(structure(&b, Color::White).backward, 0);
+    }
+
+    #[test]
+    fn test_passed_king_support() {
+        let supported = board("8/8/1k6/8/2K5/3P4/8/8 w - - 0 1");
+        let alone = board("8/8/1k6/8/8/3P4/8/7K w - - 0 1");
+        assert!(
+            evaluate(&EvalParams::DEFAULT, &supported).eg
+                > evaluate(&EvalParams::DEFAULT, &alone).eg
+        );
+    }
+
+    #[test]
+    fn test_cache_matches_fresh() {
+        let b = board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+
+        let fresh = [structure(&b, Color::White), structure(&b, Color::Black)];
+        assert_eq!(probe(&b).structure, fresh);
+
+        // Second probe is a hit, and must give the same answer.
+        assert_eq!(probe(&b).key, zobrist::pawn_hash(&b));
+        assert_eq!(probe(&b).structure, fresh);
+    }
+
+    #[test]
+    fn test_symmetric() {
+        let b = board("4k3/p1p2pp1/1p6/3P1p2/2p5/4P3/PP4PP/4K3 w - - 0 1");
+        assert_eq!(
+            evaluate(&EvalParams::DEFAULT, &b),
+            -evaluate(&EvalParams::DEFAULT, &b.mirror())
+        );
+    }
+
+    #[test]
+    fn test_no_king() {
+        // Antichess, white's king is gone but the passed pawn still counts
+        let mut b = board("4k3/8/8/3P4/8/8/8/8 w - - 0 1");
+        b.set_variant(crate::chess::Variant::Antichess);
+        assert!(evaluate(&EvalParams::DEFAULT, &b).taper(0) > 0);
+    }
+}
@@ -0,0 +1,230 @@
+use super::{get_phase, king_safety, mobility, pawns, EvalParams, Tapered, MAX_PHASE};
+use crate::chess::{Board, Color, NUM_COLORS, NUM_PIECES};
+use std::fmt;
+
+// Same as evaluate, but keeping every term apart so we can see why
+// the eval likes a position. Slow, only meant for debugging.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    Material,
+    Psqt,
+    Pawns,
+    Mobility,
+    KingSafety,
+    Tempo,
+}
+
+pub const NUM_TERMS: usize = 6;
+
+impl Term {
+    pub const ALL: [Term; NUM_TERMS] = [
+        Term::Material,
+        Term::Psqt,
+        Term::Pawns,
+        Term::Mobility,
+        Term::KingSafety,
+        Term::Tempo,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::Psqt => "PST",
+            Term::Pawns => "Pawns",
+            Term::Mobility => "Mobility",
+            Term::KingSafety => "King safety",
+            Term::Tempo => "Tempo",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalTrace {
+    // Indexed by term then color, each from that color's point of view
+    pub terms: [[Tapered; NUM_COLORS]; NUM_TERMS],
+    pub phase: i32,
+}
+
+impl EvalTrace {
+    pub fn get(&self, term: Term, color: Color) -> Tapered {
+        self.terms[term as usize][color as usize]
+    }
+
+    // White minus black
+    pub fn total(&self, term: Term) -> Tapered {
+        self.get(term, Color::White) - self.get(term, Color::Black)
+    }
+
+    // Final score from white's perspective, always the same as eval::evaluate
+    pub fn score(&self) -> i16 {
+        let mut sum = Tapered::default();
+        for &term in Term::ALL.iter() {
+            sum += self.total(term);
+        }
+        sum.taper(self.phase) as i16
+    }
+}
+
+fn material_and_psqt(params: &EvalParams, board: &Board, color: Color) -> (Tapered, Tapered) {
+    let mut material = Tapered::default();
+    let mut psqt = Tapered::default();
+
+    for piece in 0..NUM_PIECES {
+        let bitboard = board.pieces[piece] & *board.color_combined(color);
+        for sq in bitboard {
+            let i = sq.flip_vertical_if(color == Color::Black).0 as usize;
+            material += params.piece_values[piece];
+            psqt += params.psqt[piece][i];
+        }
+    }
+
+    (material, psqt)
+}
+
+pub fn trace_with_params(params: &EvalParams, board: &Board) -> EvalTrace {
+    let mut trace = EvalTrace {
+        terms: [[Tapered::default(); NUM_COLORS]; NUM_TERMS],
+        phase: get_phase(board),
+    };
+
+    for &color in [Color::White, Color::Black].iter() {
+        let (material, psqt) = material_and_psqt(params, board, color);
+        let mut set =
+            |term: Term, score: Tapered| trace.terms[term as usize][color as usize] = score;
+
+        set(Term::Material, material);
+        set(Term::Psqt, psqt);
+        set(Term::Pawns, pawns::pawns_for_color(params, board, color));
+        set(
+            Term::Mobility,
+            mobility::mobility_for_color(params, board, color),
+        );
+        set(
+            Term::KingSafety,
+            king_safety::king_safety_for_color(params, board, color),
+        );
+        if board.side_to_move == color {
+            set(Term::Tempo, params.tempo);
+        }
+    }
+
+    trace
+}
+
+pub fn trace(board: &Board) -> EvalTrace {
+    trace_with_params(&EvalParams::DEFAULT, board)
+}
+
+// Centipawns as pawns, like 1.25
+struct Pawns(i32);
+
+impl fmt::Display for Pawns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = format!("{:.2}", self.0 as f64 / 100.0);
+        f.pad(&s)
+    }
+}
+
+// Stockfish style table, every term for both colors and both phases.
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = "+-------------+-------------+-------------+-------------+";
+
+        writeln!(f, "{}", line)?;
+        writeln!(
+            f,
+            "|        Term |    White    |    Black    |    Total    |"
+        )?;
+        writeln!(
+            f,
+            "|             |   MG    EG  |   MG    EG  |   MG    EG  |"
+        )?;
+        writeln!(f, "{}", line)?;
+
+        for &term in Term::ALL.iter() {
+            let white = self.get(term, Color::White);
+            let black = self.get(term, Color::Black);
+            let total = self.total(term);
+
+            write!(f, "| {:>11} |", term.name())?;
+            for t in [white, black, total].iter() {
+                write!(f, " {:>5} {:>5} |", Pawns(t.mg), Pawns(t.eg))?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "{}", line)?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/{}", self.phase, MAX_PHASE)?;
+        write!(
+            f,
+            "Final evaluation: {} (white side)",
+            Pawns(self.score() as i32)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::STARTING_FEN;
+    use crate::eval;
+
+    const FENS: [&str; 4] = [
+        STARTING_FEN,
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    ];
+
+    #[test]
+    fn test_trace_matches_eval() {
+        for fen in FENS.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(
+                trace(&board).score(),
+                eval::get_score_ongoing(&board),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_trace_terms() {
+        let board = Board::from_start_pos();
+        let t = trace(&board);
+
+        assert_eq!(t.total(Term::Material), Tapered::default());
+        assert_eq!(t.get(Term::Tempo, Color::White), EvalParams::DEFAULT.tempo);
+        assert_eq!(t.get(Term::Tempo, Color::Black), Tapered::default());
+        assert_eq!(t.phase, MAX_PHASE);
+    }
+
+    #[test]
+    fn test_trace_mirror() {
+        for fen in FENS.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            let t = trace(&board);
+            let mirrored = trace(&board.mirror());
+
+            for &term in Term::ALL.iter() {
+                assert_eq!(
+                    t.get(term, Color::White),
+                    mirrored.get(term, Color::Black),
+                    "{} in {}",
+                    term.name(),
+                    fen
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let s = trace(&Board::from_start_pos()).to_string();
+        assert!(s.contains("|       Tempo |  0.10  0.05 |  0.00  0.00 |  0.10  0.05 |"));
+        assert!(s.ends_with("Final evaluation: 0.10 (white side)"));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod bench;
 pub mod bitboard;
 pub mod chess;
 pub mod engine;
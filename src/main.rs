@@ -1,7 +1,21 @@
+use std::env;
+use std::process;
 use yobmef::*;
 
 fn main() {
-    movegen::gen_moves_once();
+    // `yobmef bench [depth] [hash] [threads]` runs the bench and exits,
+    // with no arguments we talk UCI.
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        match uci::parse(&args.join(" ")) {
+            Some(uci::EngineMessage::Bench(opts)) => engine::Engine::bench(opts),
+            _ => {
+                eprintln!("usage: yobmef [bench [depth] [hash] [threads]]");
+                process::exit(2);
+            }
+        }
+        return;
+    }
 
     let mut engine = engine::Engine::new();
     if let Err(e) = engine.uci_loop() {
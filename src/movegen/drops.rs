@@ -0,0 +1,52 @@
+use crate::bitboard::BitBoard;
+use crate::chess::{Board, MoveKind, Movement, Piece, POCKET_PIECES};
+
+use super::helpers::{RANK_1, RANK_8};
+use super::move_list::MoveList;
+use super::targets::Targets;
+
+// Crazyhouse, anything in the pocket can go on any empty square,
+// except pawns on the first and last rank.
+pub fn get_drop_moves(board: &Board, moves: &mut MoveList, targets: &Targets) {
+    let color = targets.color;
+    let empty = !board.combined();
+
+    for &piece in POCKET_PIECES.iter() {
+        if board.pocket(color, piece) == 0 {
+            continue;
+        }
+
+        let mut squares = empty & targets.drops(piece);
+        if piece == Piece::Pawn {
+            squares &= BitBoard(!(RANK_1 | RANK_8));
+        }
+
+        for sq in squares {
+            moves.push(Movement::new(sq, sq, None).with_kind(MoveKind::Drop(piece)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::{Color, Variant};
+    use crate::movegen::helpers::moves_test;
+
+    #[test]
+    fn test_drops() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3[Pn] w - - 0 1").unwrap();
+        board.set_variant(Variant::Crazyhouse);
+        moves_test(&board, "P@e2 P@a7 P@h4", "P@e8 P@h1 P@a1 N@e4 Q@e4");
+
+        board.side_to_move = Color::Black;
+        moves_test(&board, "N@e4 N@a8 N@h1", "N@e1 P@e4");
+    }
+
+    #[test]
+    fn test_drop_blocks_check() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/r3K3[N] w - - 0 1").unwrap();
+        board.set_variant(Variant::Crazyhouse);
+        moves_test(&board, "N@b1 N@c1 N@d1", "N@e4 N@d2 N@a8");
+    }
+}
@@ -2,7 +2,7 @@
 
 use super::MoveGen;
 use crate::bitboard::BitBoard;
-use crate::chess::{Board, Movement, Square};
+use crate::chess::{Board, MoveKind, Movement, Square};
 
 // We could inline shifts of the different files but this is more readable
 pub const A_FILE: u64 = 0x101010101010101;
@@ -30,6 +30,15 @@ pub const NOT_GH_FILE: u64 = !(G_FILE | H_FILE);
 
 pub const NOT_EDGES: u64 = !(A_FILE | H_FILE | RANK_1 | RANK_8);
 
+// Capture if there's a piece on to_sq, quiet otherwise. The generators
+// already masked out our own pieces.
+#[inline]
+pub fn capture_or_quiet(board: &Board, to_sq: Square) -> MoveKind {
+    board
+        .piece_on(to_sq)
+        .map_or(MoveKind::Quiet, MoveKind::Capture)
+}
+
 pub fn moves_to_str(moves: &[Movement]) -> String {
     let s = moves
         .iter()
@@ -49,7 +58,7 @@ pub fn moves_test(board: &Board, legal: &str, illegal: &str) {
     let legal_str = moves_to_str(&moves);
 
     for lan in legal.split(' ') {
-        if !moves.contains(&Movement::from_notation(lan).unwrap()) {
+        if !moves.contains(&board.parse_move(lan).unwrap()) {
             eprintln!("{}", board);
             panic!("{} should be legal, legal moves: {}", lan, legal_str);
         }
@@ -57,7 +66,7 @@ pub fn moves_test(board: &Board, legal: &str, illegal: &str) {
 
     if !illegal.is_empty() {
         for lan in illegal.split(' ') {
-            if moves.contains(&Movement::from_notation(lan).unwrap()) {
+            if moves.contains(&board.parse_move(lan).unwrap()) {
                 eprintln!("{}", board);
                 panic!("{} should be illegal, legal moves: {}", lan, legal_str);
             }
@@ -90,7 +99,7 @@ pub fn bitboard_test(board: &BitBoard, included: &str, excluded: &str) {
 // TODO: Refactor to use a hashset or a double `contains()` to better show how order doesn't
 // matter.
 pub fn assert_moves(board: &Board, mut got_moves: Vec<Movement>, moves: &str) {
-    let mut want_moves = vec_moves(moves);
+    let mut want_moves = vec_moves(board, moves);
     want_moves.sort_by_key(|m| m.hash());
     got_moves.sort_by_key(|m| m.hash());
 
@@ -102,10 +111,10 @@ pub fn assert_moves(board: &Board, mut got_moves: Vec<Movement>, moves: &str) {
     }
 }
 
-fn vec_moves(moves_str: &str) -> Vec<Movement> {
+fn vec_moves(board: &Board, moves_str: &str) -> Vec<Movement> {
     let mut moves = Vec::new();
     for lan in moves_str.split(' ') {
-        moves.push(Movement::from_notation(lan).unwrap())
+        moves.push(board.parse_move(lan).unwrap())
     }
     moves
 }
@@ -1,17 +1,23 @@
 use super::helpers::*;
+use super::move_list::MoveList;
+use super::targets::{GenType, Targets};
 use crate::bitboard::*;
 use crate::chess::*;
 
-static mut KING_MOVES: [BitBoard; 64] = [BitBoard::empty(); 64];
+// Worked out at compile time
+static KING_MOVES: [BitBoard; 64] = gen_king_moves();
 
-fn king_moves(square: Square) -> BitBoard {
-    unsafe { KING_MOVES[square.0 as usize] }
+pub fn king_moves(square: Square) -> BitBoard {
+    KING_MOVES[square.0 as usize]
 }
 
-pub fn gen_king_moves() {
-    for from_sq_index in 0..64 {
+const fn gen_king_moves() -> [BitBoard; 64] {
+    let mut table = [BitBoard::empty(); 64];
+
+    let mut from_sq_index = 0;
+    while from_sq_index < 64 {
         let mut king_moves: u64 = 0;
-        let only_from_sq = 1 << from_sq_index;
+        let only_from_sq: u64 = 1 << from_sq_index;
 
         king_moves |= (only_from_sq << 8) & !RANK_1; // Up
         king_moves |= (only_from_sq << 9) & !(RANK_1 | A_FILE); // Up-right
@@ -24,10 +30,11 @@ pub fn gen_king_moves() {
         king_moves |= (only_from_sq >> 1) & !H_FILE; // Left
         king_moves |= (only_from_sq << 1) & !A_FILE; // Right
 
-        unsafe {
-            KING_MOVES[from_sq_index as usize] = BitBoard(king_moves);
-        }
+        table[from_sq_index] = BitBoard(king_moves);
+        from_sq_index += 1;
     }
+
+    table
 }
 
 pub fn get_king_attacks(board: &Board, color: Color) -> BitBoard {
@@ -36,33 +43,39 @@ pub fn get_king_attacks(board: &Board, color: Color) -> BitBoard {
     let our_pieces = *board.color_combined(color);
     let king = *board.pieces(Piece::King) & our_pieces;
 
-    #[allow(clippy::never_loop)]
+    // More than one king only happens in antichess
     for from_sq in king {
         attacks |= king_moves(from_sq);
-
-        // If we have more then one king; we've got bigger problems
-        break;
     }
 
     attacks
 }
 
-pub fn get_king_moves(board: &Board, moves: &mut Vec<Movement>, color: Color) {
+pub fn get_king_moves(board: &Board, moves: &mut MoveList, targets: &Targets) {
+    let color = targets.color;
     let our_pieces = *board.color_combined(color);
     let king = *board.pieces(Piece::King) & our_pieces;
 
-    let king_sq_index = king.0.trailing_zeros();
-    let king_sq = Square(king_sq_index as u8);
-
-    let moves_bitboard = king_moves(king_sq) & !our_pieces;
+    // In antichess we can have no king, or a few of them
+    for king_sq in king {
+        for to_sq in king_moves(king_sq) & targets.to_squares(Piece::King, king_sq) {
+            let kind = capture_or_quiet(board, to_sq);
+            let movement = Movement::new(king_sq, to_sq, None).with_kind(kind);
+            moves.push(movement);
+        }
+    }
 
-    for to_sq in moves_bitboard {
-        let movement = Movement::new(king_sq, to_sq, None);
-        moves.push(movement);
+    // Castling is quiet, and never gets you out of check
+    if king.count_ones() != 1
+        || !matches!(
+            targets.gen,
+            GenType::All | GenType::Quiets | GenType::QuietChecks
+        )
+    {
+        return;
     }
+    let king_sq = board.king(color);
 
-    let attacks = board.attacked(color.other());
-    let our_rooks = *board.pieces(Piece::Rook) & our_pieces;
     let all_pieces = our_pieces | board.color_combined(color.other());
 
     CastlingSide::of_color(color).iter().for_each(|side| {
@@ -70,20 +83,32 @@ pub fn get_king_moves(board: &Board, moves: &mut Vec<Movement>, color: Color) {
             return;
         }
 
-        let middle = side.get_castling_middle();
-        let not_attacked = side.get_castling_not_attacked();
-        let attacked = (attacks & not_attacked).count_ones() > 0;
-        let blocked = (all_pieces & middle).count_ones() > 0;
+        // Works for Chess960 too, the king and rook can start anywhere on the back
+        // rank. Everything they pass over has to be empty, apart from themselves,
+        // and the king can't pass through check.
+        let rook_sq = board.castling_rook(*side);
+        if !(*board.pieces(Piece::Rook) & our_pieces).get(rook_sq) {
+            return;
+        }
+
+        let king_path = rank_span(king_sq, side.king_destination());
+        let rook_path = rank_span(rook_sq, side.rook_destination());
+        let blockers =
+            all_pieces & !BitBoard::from_square(king_sq) & !BitBoard::from_square(rook_sq);
 
-        // This is really bad and horrible and needs optimization
-        let king_movement = side.get_king_movement();
-        let king_placed = king_movement.from_square == king_sq;
+        let blocked = ((king_path | rook_path) & blockers).count_ones() > 0;
+        let attacked = king_path
+            .into_iter()
+            .any(|sq| board.is_attacked_by(sq, color.other()));
 
-        let rook_movement = side.get_rook_movement();
-        let rook_placed = our_rooks.get(rook_movement.from_square);
+        if blocked || attacked {
+            return;
+        }
 
-        if !blocked && !attacked && king_placed && rook_placed {
-            moves.push(side.get_king_movement());
+        // Too many ways for castling to give check, just try it
+        let castle = Movement::new(king_sq, rook_sq, None).with_kind(MoveKind::Castle(*side));
+        if targets.gen != GenType::QuietChecks || board.make_move(&castle).in_check() {
+            moves.push(castle);
         }
     });
 }
@@ -1,16 +1,22 @@
-use super::helpers::{NOT_AB_FILE, NOT_A_FILE, NOT_GH_FILE, NOT_H_FILE};
+use super::helpers::{capture_or_quiet, NOT_AB_FILE, NOT_A_FILE, NOT_GH_FILE, NOT_H_FILE};
+use super::move_list::MoveList;
+use super::targets::Targets;
 use crate::chess::{Board, Movement, Piece, Square};
 use crate::{bitboard::BitBoard, chess::Color};
 
-static mut KNIGHT_MOVES: [BitBoard; 64] = [BitBoard::empty(); 64];
+// Worked out at compile time
+static KNIGHT_MOVES: [BitBoard; 64] = gen_knight_moves();
 
-fn knight_moves(square: Square) -> BitBoard {
-    unsafe { KNIGHT_MOVES[square.0 as usize] }
+pub fn knight_moves(square: Square) -> BitBoard {
+    KNIGHT_MOVES[square.0 as usize]
 }
 
-pub fn gen_knight_moves() {
-    for from_sq_index in 0..64 {
-        let only_from_sq = 1 << from_sq_index;
+const fn gen_knight_moves() -> [BitBoard; 64] {
+    let mut table = [BitBoard::empty(); 64];
+
+    let mut from_sq_index = 0;
+    while from_sq_index < 64 {
+        let only_from_sq: u64 = 1 << from_sq_index;
 
         let mut knight_moves: u64 = 0;
         knight_moves |= (only_from_sq << 17) & NOT_A_FILE;
@@ -22,10 +28,11 @@ pub fn gen_knight_moves() {
         knight_moves |= (only_from_sq >> 10) & NOT_GH_FILE;
         knight_moves |= (only_from_sq >> 17) & NOT_H_FILE;
 
-        unsafe {
-            KNIGHT_MOVES[from_sq_index as usize] = BitBoard(knight_moves);
-        }
+        table[from_sq_index] = BitBoard(knight_moves);
+        from_sq_index += 1;
     }
+
+    table
 }
 
 pub fn get_knight_attacks(board: &Board, color: Color) -> BitBoard {
@@ -41,15 +48,16 @@ pub fn get_knight_attacks(board: &Board, color: Color) -> BitBoard {
     attacks
 }
 
-pub fn get_knight_moves(board: &Board, moves: &mut Vec<Movement>, color: Color) {
-    let my_pieces = *board.color_combined(color);
+pub fn get_knight_moves(board: &Board, moves: &mut MoveList, targets: &Targets) {
+    let my_pieces = *board.color_combined(targets.color);
     let my_knights = *board.pieces(Piece::Knight) & my_pieces;
 
     for from_sq in my_knights {
-        let moves_bitboard = knight_moves(from_sq) & !my_pieces;
+        let moves_bitboard = knight_moves(from_sq) & targets.to_squares(Piece::Knight, from_sq);
 
         for to_sq in moves_bitboard {
-            moves.push(Movement::new(from_sq, to_sq, None));
+            let kind = capture_or_quiet(board, to_sq);
+            moves.push(Movement::new(from_sq, to_sq, None).with_kind(kind));
         }
     }
 }
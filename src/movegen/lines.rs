@@ -0,0 +1,110 @@
+use crate::bitboard::BitBoard;
+use crate::chess::Square;
+
+// Squares strictly between two squares on a rank, file or diagonal, and the
+// whole line through them edge to edge. Empty if they don't share a line.
+static BETWEEN: [[BitBoard; 64]; 64] = gen_lines().0;
+static LINE: [[BitBoard; 64]; 64] = gen_lines().1;
+
+#[inline]
+pub fn between(a: Square, b: Square) -> BitBoard {
+    BETWEEN[a.0 as usize][b.0 as usize]
+}
+
+#[inline]
+pub fn line(a: Square, b: Square) -> BitBoard {
+    LINE[a.0 as usize][b.0 as usize]
+}
+
+const fn sign(n: i8) -> i8 {
+    if n > 0 {
+        1
+    } else if n < 0 {
+        -1
+    } else {
+        0
+    }
+}
+
+const fn on_board(rank: i8, file: i8) -> bool {
+    rank >= 0 && rank < 8 && file >= 0 && file < 8
+}
+
+type LineTable = [[BitBoard; 64]; 64];
+
+const fn gen_lines() -> (LineTable, LineTable) {
+    let mut between = [[BitBoard::empty(); 64]; 64];
+    let mut line = [[BitBoard::empty(); 64]; 64];
+
+    let mut a = 0;
+    while a < 64 {
+        let mut b = 0;
+        while b < 64 {
+            let (a_rank, a_file) = ((a / 8) as i8, (a % 8) as i8);
+            let (b_rank, b_file) = ((b / 8) as i8, (b % 8) as i8);
+            let (dr, df) = (b_rank - a_rank, b_file - a_file);
+            let aligned = dr == 0 || df == 0 || dr == df || dr == -df;
+
+            if a != b && aligned {
+                let (step_rank, step_file) = (sign(dr), sign(df));
+
+                // Walk from a to b
+                let mut squares = 0;
+                let (mut rank, mut file) = (a_rank + step_rank, a_file + step_file);
+                while rank != b_rank || file != b_file {
+                    squares |= 1 << (rank * 8 + file);
+                    rank += step_rank;
+                    file += step_file;
+                }
+                between[a][b] = BitBoard(squares);
+
+                // Then from a out to both edges
+                let mut full = 1 << a;
+                let (mut rank, mut file) = (a_rank + step_rank, a_file + step_file);
+                while on_board(rank, file) {
+                    full |= 1 << (rank * 8 + file);
+                    rank += step_rank;
+                    file += step_file;
+                }
+                let (mut rank, mut file) = (a_rank - step_rank, a_file - step_file);
+                while on_board(rank, file) {
+                    full |= 1 << (rank * 8 + file);
+                    rank -= step_rank;
+                    file -= step_file;
+                }
+                line[a][b] = BitBoard(full);
+            }
+
+            b += 1;
+        }
+        a += 1;
+    }
+
+    (between, line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::helpers::bitboard_test;
+
+    fn sq(s: &str) -> Square {
+        Square::from_notation(s).unwrap()
+    }
+
+    #[test]
+    fn test_between() {
+        bitboard_test(&between(sq("b2"), sq("f6")), "c3 d4 e5", "b2 f6 g7 a1");
+        bitboard_test(&between(sq("e8"), sq("e1")), "e2 e7", "e1 e8 d4");
+        assert_eq!(between(sq("e1"), sq("f3")), BitBoard::empty());
+        assert_eq!(between(sq("e1"), sq("f2")), BitBoard::empty());
+        assert_eq!(between(sq("a1"), sq("b2")), between(sq("b2"), sq("a1")));
+    }
+
+    #[test]
+    fn test_line() {
+        bitboard_test(&line(sq("c3"), sq("e5")), "a1 b2 c3 e5 h8", "a2 c4");
+        bitboard_test(&line(sq("d1"), sq("d2")), "d1 d4 d8", "e1");
+        assert_eq!(line(sq("e1"), sq("f3")), BitBoard::empty());
+    }
+}
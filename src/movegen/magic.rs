@@ -1,152 +1,234 @@
-use rand::rngs::StdRng;
-use rand::SeedableRng;
+use std::sync::OnceLock;
 
-use super::magic_utils::{get_occupancy_mask, get_questions_and_answers, NUM_MOVES};
+use super::helpers::capture_or_quiet;
+use super::magic_utils::{get_occupancy_mask, get_questions_and_answers};
+use super::move_list::MoveList;
+use super::targets::Targets;
 
 use crate::bitboard::BitBoard;
 use crate::chess::{Board, Color, Movement, Piece, Square};
 
-static mut MOVES: [BitBoard; NUM_MOVES] = [BitBoard::empty(); NUM_MOVES];
-static mut ROOK_MAGICS: [MagicSquare; 64] = [MagicSquare::empty(); 64];
-static mut BISHOP_MAGICS: [MagicSquare; 64] = [MagicSquare::empty(); 64];
+// Found with the seeded search in the tests (test_find_magics) and pasted
+// here, so we don't search for them every time we start up.
+const ROOK_MAGIC_NUMBERS: [u64; 64] = [
+    0x0080008010c002a0,
+    0x0040022004403000,
+    0x0180188010002001,
+    0x0100184410012100,
+    0x4200080200203015,
+    0x4600020030280904,
+    0x4400128810090402,
+    0x0080102080004100,
+    0x1000800080204004,
+    0x9000c00441201000,
+    0x0820802000b00080,
+    0x00c1801000080080,
+    0x0300808008000c00,
+    0x0402000804020010,
+    0x0061000200140100,
+    0x0021000180450022,
+    0x0000208002400280,
+    0x571010c000402004,
+    0x0820078020100084,
+    0x000042002010aa00,
+    0x4242050011012800,
+    0x0202008024000280,
+    0xc00b808002000100,
+    0xa880660002440081,
+    0x0003400280002098,
+    0x0870004040022014,
+    0x1302018200401221,
+    0x0202100100092100,
+    0x0006000a00041020,
+    0x9800020080040080,
+    0x8400010080800200,
+    0x4a01000100008042,
+    0x0000401080800060,
+    0x3010042008400048,
+    0x800500a001004110,
+    0x800a809804801000,
+    0x0a01800400804800,
+    0x0c04800400804200,
+    0x0000132204009008,
+    0x04e000440a000c85,
+    0x0200204010808008,
+    0x1244400060008080,
+    0x4010128204420020,
+    0x1130008008008030,
+    0x0000040008008080,
+    0x0c4a002010040400,
+    0x11001008860c0001,
+    0x02000a8444020005,
+    0x0840048004d06080,
+    0x0000c009200d8080,
+    0x8400100020008880,
+    0x0828005c50008080,
+    0x0044018004480080,
+    0x0002009008448200,
+    0x2042680201100c00,
+    0x4400458401004200,
+    0x18c0801201004426,
+    0x18c0801201004426,
+    0x0201111842002082,
+    0x010600a00c1810c2,
+    0x010600a00c1810c2,
+    0x001100488a040001,
+    0x5040008308101204,
+    0x9200030cc2240882,
+];
+
+const BISHOP_MAGIC_NUMBERS: [u64; 64] = [
+    0x0418281002820010,
+    0x0188104480830000,
+    0x0008080102204048,
+    0x3808060445820200,
+    0x00e9104080200200,
+    0x8081040241050080,
+    0x8004021202610000,
+    0xc2a1040042080451,
+    0x0a21200403420400,
+    0x0800081009484102,
+    0x2010a40142020920,
+    0x0082090c01040000,
+    0x9044040421044408,
+    0x4000811008040404,
+    0x0880040908084421,
+    0x00000a0482111040,
+    0x21200410200600c0,
+    0xc002000802040400,
+    0x0081000818002080,
+    0x601800522200400a,
+    0x0905002820084140,
+    0x0082000100b08403,
+    0x1803010401180200,
+    0x0082000100b08403,
+    0x0008208084208a04,
+    0x4210440006080200,
+    0x0084020040420144,
+    0x048a012082008200,
+    0x05c0840100802018,
+    0x0006930202010080,
+    0x00091e0089009010,
+    0x1080468040420811,
+    0x00042c2000442110,
+    0x00042c2000442110,
+    0x00092088011010a0,
+    0x6888040400080211,
+    0x5308100400024102,
+    0x1000810100061000,
+    0x8802020201040480,
+    0x2301010028020208,
+    0x4001049004084064,
+    0x4044120150120c00,
+    0x2902320104401000,
+    0x0100024208001080,
+    0x4001202008800108,
+    0x66902000a1004020,
+    0x201010b1010800c0,
+    0x4022523401600104,
+    0x2080640420085201,
+    0x0092090082500044,
+    0x2000004044305010,
+    0xe808880684110200,
+    0x0c04184010444000,
+    0x2000100250010100,
+    0x0260204a0200c804,
+    0x07102419e0820120,
+    0x0062020a00862881,
+    0x0062020a00862881,
+    0x3004865040445030,
+    0x082101042420a800,
+    0x0108004094208201,
+    0x0001c0a054b02285,
+    0x2121401802a08608,
+    0x0710020800440440,
+];
 
 #[derive(Debug, Clone, Copy)]
-pub struct MagicSquare {
+struct MagicSquare {
     number: BitBoard,
     occupancy_mask: BitBoard,
     offset: u32,
     right_shift: u8,
-    empty: bool,
 }
 
 impl MagicSquare {
-    fn new(
-        number: BitBoard,
-        occupancy_mask: BitBoard,
-        offset: u32,
-        right_shift: u8,
-    ) -> MagicSquare {
-        MagicSquare {
-            number,
-            occupancy_mask,
-            offset,
-            right_shift,
-            empty: false,
-        }
-    }
-
-    const fn empty() -> MagicSquare {
-        MagicSquare {
-            number: BitBoard::empty(),
-            occupancy_mask: BitBoard::empty(),
-            offset: 0,
-            right_shift: 0,
-            empty: true,
-        }
-    }
-
-    pub fn lookup_hash(&self, occupancy: &BitBoard) -> BitBoard {
-        if self.empty {
-            panic!(
-                "tried to lookup hash on an empty magic square, did you forget to run gen_moves?"
-            );
-        }
-
+    #[inline]
+    fn index(&self, occupancy: &BitBoard) -> usize {
         let raw_hash = self.number * (self.occupancy_mask & occupancy);
         let shifted_hash = (raw_hash.0 as usize) >> (self.right_shift as usize);
-        let hash = (self.offset as usize) + shifted_hash;
-
-        unsafe { MOVES[hash] }
+        (self.offset as usize) + shifted_hash
     }
 }
 
-static SEEDS: [u64; 8] = [8198, 15098, 15153, 12593, 16340, 19763, 55569, 7831];
-
-// TODO: Go through, fully re-comprehend, and refactor this BS
-fn gen_single_magic(from_sq: Square, piece: Piece, cur_offset: usize) -> usize {
-    let (questions, answers) = get_questions_and_answers(from_sq, piece);
-
-    let occupancy_mask = get_occupancy_mask(from_sq, piece);
-    let mut new_magic = MagicSquare::new(
-        BitBoard::empty(),
-        occupancy_mask,
-        cur_offset as u32,
-        (questions.len().leading_zeros() + 1) as u8,
-    );
-
-    let mut done = false;
-    let mut rng = StdRng::seed_from_u64(SEEDS[from_sq.rank() as usize]);
+struct Magics {
+    rook: [MagicSquare; 64],
+    bishop: [MagicSquare; 64],
+    moves: Vec<BitBoard>,
+}
 
-    while !done {
-        let magic_bitboard = BitBoard::random(&mut rng);
+static MAGICS: OnceLock<Magics> = OnceLock::new();
 
-        // DEAR GOD
-        if (occupancy_mask * magic_bitboard).count_ones() < 6 {
-            continue;
-        }
+// Filled in the first time anyone asks for a sliding move, a few milliseconds
+#[inline]
+fn magics() -> &'static Magics {
+    MAGICS.get_or_init(Magics::new)
+}
 
-        // AAAAAAAAAAAAAAAA
-        let mut new_answers = vec![BitBoard::empty(); questions.len()];
-        done = true;
-        for i in 0..questions.len() {
-            let hash = (magic_bitboard * questions[i]).0 >> (new_magic.right_shift as u64);
-            let j = hash as usize;
-            if new_answers[j] == BitBoard::empty() {
-                new_answers[j] = answers[i];
-            } else {
-                done = false;
-                break;
+impl Magics {
+    fn new() -> Magics {
+        let mut moves = Vec::new();
+
+        let mut init = |sq: Square, piece: Piece, number: u64| {
+            let (questions, answers) = get_questions_and_answers(sq, piece);
+            let magic = MagicSquare {
+                number: BitBoard(number),
+                occupancy_mask: get_occupancy_mask(sq, piece),
+                offset: moves.len() as u32,
+                right_shift: (questions.len().leading_zeros() + 1) as u8,
+            };
+
+            moves.resize(moves.len() + questions.len(), BitBoard::empty());
+            for (question, answer) in questions.iter().zip(answers) {
+                let i = magic.index(question);
+                // Two occupancies can share a slot, but only if they have the same moves
+                debug_assert!(moves[i] == BitBoard::empty() || moves[i] == answer);
+                moves[i] = answer;
             }
-        }
 
-        if done {
-            new_magic.number = magic_bitboard;
-        }
-    }
+            magic
+        };
 
-    unsafe {
-        if piece == Piece::Rook {
-            ROOK_MAGICS[from_sq.0 as usize] = new_magic;
-        } else {
-            BISHOP_MAGICS[from_sq.0 as usize] = new_magic;
-        }
+        let bishop =
+            std::array::from_fn(|i| init(Square(i as u8), Piece::Bishop, BISHOP_MAGIC_NUMBERS[i]));
+        let rook =
+            std::array::from_fn(|i| init(Square(i as u8), Piece::Rook, ROOK_MAGIC_NUMBERS[i]));
 
-        for i in 0..questions.len() {
-            let hash = (new_magic.number * questions[i]) >> (new_magic.right_shift as u64);
-            let j = hash.0 as usize;
-            MOVES[(new_magic.offset as usize) + j] = answers[i];
+        Magics {
+            rook,
+            bishop,
+            moves,
         }
     }
 
-    cur_offset + questions.len()
-}
-
-pub fn gen_all_magics() {
-    let mut cur_offset = 0;
-
-    for sq_index in 0..64 {
-        cur_offset = gen_single_magic(Square(sq_index), Piece::Bishop, cur_offset);
-    }
-    for sq_index in 0..64 {
-        cur_offset = gen_single_magic(Square(sq_index), Piece::Rook, cur_offset);
+    #[inline]
+    fn lookup(&self, magic: &MagicSquare, occupancy: &BitBoard) -> BitBoard {
+        self.moves[magic.index(occupancy)]
     }
 }
 
-fn get_sliding_moves_bb(sq: Square, piece: Piece, occupancy: &BitBoard) -> BitBoard {
-    unsafe {
-        if piece == Piece::Rook {
-            let magic = ROOK_MAGICS[sq.0 as usize];
-            magic.lookup_hash(occupancy)
-        } else if piece == Piece::Bishop {
-            let magic = BISHOP_MAGICS[sq.0 as usize];
-            magic.lookup_hash(occupancy)
-        } else if piece == Piece::Queen {
-            let bishop_magic = BISHOP_MAGICS[sq.0 as usize];
-            let rook_magic = ROOK_MAGICS[sq.0 as usize];
-            bishop_magic.lookup_hash(occupancy) | rook_magic.lookup_hash(occupancy)
-        } else {
-            panic!("{:?} is not a sliding piece", piece);
+pub fn get_sliding_moves_bb(sq: Square, piece: Piece, occupancy: &BitBoard) -> BitBoard {
+    let magics = magics();
+    let sq = sq.0 as usize;
+
+    match piece {
+        Piece::Rook => magics.lookup(&magics.rook[sq], occupancy),
+        Piece::Bishop => magics.lookup(&magics.bishop[sq], occupancy),
+        Piece::Queen => {
+            magics.lookup(&magics.bishop[sq], occupancy)
+                | magics.lookup(&magics.rook[sq], occupancy)
         }
+        _ => panic!("{:?} is not a sliding piece", piece),
     }
 }
 
@@ -174,9 +256,9 @@ pub fn get_sliding_attacks(board: &Board, color: Color) -> BitBoard {
     attacks
 }
 
-pub fn get_sliding_moves(board: &Board, moves: &mut Vec<Movement>, color: Color) {
+pub fn get_sliding_moves(board: &Board, moves: &mut MoveList, targets: &Targets) {
     let all_pieces = board.combined();
-    let my_pieces = *board.color_combined(color);
+    let my_pieces = *board.color_combined(targets.color);
 
     let my_queens = *board.pieces(Piece::Queen) & my_pieces;
     let my_rooks = *board.pieces(Piece::Rook) & my_pieces;
@@ -184,18 +266,21 @@ pub fn get_sliding_moves(board: &Board, moves: &mut Vec<Movement>, color: Color)
     let my_sliding_pieces = my_rooks | my_bishops | my_queens;
 
     for from_sq in my_sliding_pieces {
-        let moves_bitboard = if my_rooks.get(from_sq) {
-            get_sliding_moves_bb(from_sq, Piece::Rook, &all_pieces) & !my_pieces
+        let piece = if my_rooks.get(from_sq) {
+            Piece::Rook
         } else if my_bishops.get(from_sq) {
-            get_sliding_moves_bb(from_sq, Piece::Bishop, &all_pieces) & !my_pieces
+            Piece::Bishop
         } else if my_queens.get(from_sq) {
-            get_sliding_moves_bb(from_sq, Piece::Queen, &all_pieces) & !my_pieces
+            Piece::Queen
         } else {
             panic!("my_sliding_pieces contains a non sliding piece");
         };
+        let moves_bitboard =
+            get_sliding_moves_bb(from_sq, piece, &all_pieces) & targets.to_squares(piece, from_sq);
 
         for to_sq in moves_bitboard {
-            moves.push(Movement::new(from_sq, to_sq, None));
+            let kind = capture_or_quiet(board, to_sq);
+            moves.push(Movement::new(from_sq, to_sq, None).with_kind(kind));
         }
     }
 }
@@ -204,12 +289,70 @@ pub fn get_sliding_moves(board: &Board, moves: &mut Vec<Movement>, color: Color)
 pub mod tests {
     use super::*;
     use crate::movegen::helpers::{bitboard_test, moves_test};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    static SEEDS: [u64; 8] = [8198, 15098, 15153, 12593, 16340, 19763, 55569, 7831];
+
+    // Try random numbers until every occupancy hashes to its own slot
+    fn find_magic(sq: Square, piece: Piece) -> u64 {
+        let (questions, _) = get_questions_and_answers(sq, piece);
+        let occupancy_mask = get_occupancy_mask(sq, piece);
+        let right_shift = questions.len().leading_zeros() + 1;
+        let mut rng = StdRng::seed_from_u64(SEEDS[sq.rank() as usize]);
+
+        loop {
+            let number = BitBoard::random(&mut rng);
+            if (occupancy_mask * number).count_ones() < 6 {
+                continue;
+            }
+
+            let mut used = vec![false; questions.len()];
+            let unique = questions.iter().all(|q| {
+                let j = ((number * *q).0 >> right_shift) as usize;
+                !std::mem::replace(&mut used[j], true)
+            });
+            if unique {
+                return number.0;
+            }
+        }
+    }
+
+    // Run with --ignored --nocapture to get new numbers
+    #[ignore]
+    #[test]
+    fn test_find_magics() {
+        for &(piece, numbers) in [
+            (Piece::Rook, &ROOK_MAGIC_NUMBERS),
+            (Piece::Bishop, &BISHOP_MAGIC_NUMBERS),
+        ]
+        .iter()
+        {
+            for (sq, &number) in numbers.iter().enumerate() {
+                let found = find_magic(Square(sq as u8), piece);
+                println!("{:?} {}: 0x{:016x}", piece, sq, found);
+                assert_eq!(found, number);
+            }
+        }
+    }
+
+    #[test]
+    fn test_all_lookups() {
+        // Every occupancy of every square gives the moves we'd get by walking the rays
+        for sq in 0..64 {
+            let sq = Square(sq);
+            for &piece in [Piece::Rook, Piece::Bishop].iter() {
+                let (questions, answers) = get_questions_and_answers(sq, piece);
+                for (question, answer) in questions.iter().zip(answers) {
+                    assert_eq!(get_sliding_moves_bb(sq, piece, question), answer);
+                }
+            }
+        }
+    }
 
     #[test]
     fn test_rook_move_lookup() {
-        gen_all_magics();
         let sq = Square::from_notation("d5").unwrap();
-
         let mut occupancy = BitBoard::empty();
         occupancy.flip_mut(Square::from_notation("d3").unwrap());
         occupancy.flip_mut(Square::from_notation("h5").unwrap());
@@ -220,7 +363,6 @@ pub mod tests {
 
     #[test]
     fn test_bishop_move_lookup() {
-        gen_all_magics();
         let sq = Square::from_notation("g3").unwrap();
 
         let mut occupancy = BitBoard::empty();
@@ -3,13 +3,6 @@ use crate::chess::{Piece, Square};
 
 use super::helpers::NOT_EDGES;
 
-const ROOK_BITS: usize = 12;
-const BISHOP_BITS: usize = 9;
-
-// Just some math magic involving powers of two,
-// don't try to understand it
-pub const NUM_MOVES: usize = (64 * (1 << ROOK_BITS)) + (64 * (1 << BISHOP_BITS));
-
 fn get_bishop_rays(from_sq: Square) -> BitBoard {
     let mut rays = BitBoard::empty();
 
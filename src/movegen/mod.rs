@@ -1,31 +1,26 @@
-use crate::chess::{Board, Movement};
+use crate::chess::{Board, Movement, Piece, Square, Variant};
 use crate::{bitboard::BitBoard, chess::Color};
-use std::sync::Once;
 
+mod drops;
 mod helpers;
 mod king;
 mod knight;
+mod lines;
 mod magic;
 mod magic_utils;
+mod move_list;
 mod pawn;
+mod perft;
+mod targets;
 
-static START: Once = Once::new();
-
-pub fn gen_moves_once() {
-    START.call_once(|| {
-        gen_moves();
-    });
-}
-
-fn gen_moves() {
-    pawn::gen_pawn_moves();
-    knight::gen_knight_moves();
-    king::gen_king_moves();
-    magic::gen_all_magics();
-}
+pub use lines::{between, line};
+pub use move_list::{MoveList, MAX_MOVES};
+pub use perft::{perft, perft_divide, PerftTable};
+pub use targets::GenType;
+use targets::Targets;
 
 pub struct MoveGen {
-    pseudolegal: Vec<Movement>,
+    pseudolegal: MoveList,
     index: usize,
     board: Board,
     iterator_mask: BitBoard,
@@ -33,7 +28,32 @@ pub struct MoveGen {
 
 impl MoveGen {
     pub fn new_legal(board: &Board) -> MoveGen {
-        let pseudolegal = get_pseudolegal_moves(board);
+        MoveGen::new(board, GenType::All)
+    }
+
+    pub fn new(board: &Board, gen: GenType) -> MoveGen {
+        // Games the variant rules already ended have no moves
+        let mut pseudolegal = if board.variant_result().is_some() {
+            MoveList::new()
+        } else {
+            get_moves(board, gen)
+        };
+
+        // Antichess, if you can take you have to. Promotions are in with the
+        // captures, so we have to look for real ones, and quiets have to know
+        // if there are any.
+        if board.variant == Variant::Antichess {
+            let forced = match gen {
+                GenType::All | GenType::Captures => pseudolegal.iter().any(|mv| mv.is_capture()),
+                _ => get_moves(board, GenType::Captures)
+                    .iter()
+                    .any(|mv| mv.is_capture()),
+            };
+            if forced {
+                pseudolegal.retain(|mv| mv.is_capture());
+            }
+        }
+
         MoveGen {
             pseudolegal,
             index: 0,
@@ -63,14 +83,15 @@ impl Iterator for MoveGen {
                 continue;
             }
 
-            // After the move, are we in check?
+            // No king to leave in check in antichess, everything goes
+            if !self.board.variant.has_royal_king() {
+                return Some(mv.clone());
+            }
 
+            // After the move, are we in check?
+            let us = self.board.side_to_move;
             let after_move = self.board.make_move(mv);
-            let attacks = after_move.attacked(after_move.side_to_move);
-
-            let only_our_king = 1 << after_move.king(self.board.side_to_move).0;
-            let is_in_check = (attacks.0 & only_our_king).count_ones() > 0;
-            if !is_in_check {
+            if !after_move.is_attacked_by(after_move.king(us), us.other()) {
                 return Some(mv.clone());
             }
         }
@@ -79,12 +100,20 @@ impl Iterator for MoveGen {
     }
 }
 
-pub fn get_pseudolegal_moves(board: &Board) -> Vec<Movement> {
-    let mut moves = Vec::new();
-    pawn::get_pawn_moves(board, &mut moves, board.side_to_move);
-    knight::get_knight_moves(board, &mut moves, board.side_to_move);
-    king::get_king_moves(board, &mut moves, board.side_to_move);
-    magic::get_sliding_moves(board, &mut moves, board.side_to_move);
+pub fn get_pseudolegal_moves(board: &Board) -> MoveList {
+    get_moves(board, GenType::All)
+}
+
+fn get_moves(board: &Board, gen: GenType) -> MoveList {
+    let targets = Targets::new(board, gen);
+    let mut moves = MoveList::new();
+    pawn::get_pawn_moves(board, &mut moves, &targets);
+    knight::get_knight_moves(board, &mut moves, &targets);
+    king::get_king_moves(board, &mut moves, &targets);
+    magic::get_sliding_moves(board, &mut moves, &targets);
+    if board.variant == Variant::Crazyhouse {
+        drops::get_drop_moves(board, &mut moves, &targets);
+    }
     moves
 }
 
@@ -97,17 +126,32 @@ pub fn get_attacked_squares(board: &Board, color: Color) -> BitBoard {
     attacks
 }
 
-// For debugging, used in tests and for a debug command 'go perft depth'
-pub fn perft(board: &Board, depth: u16) -> u64 {
-    if depth == 1 {
-        MoveGen::new_legal(board).count() as u64
-    } else {
-        let mut n = 0;
-        for mv in MoveGen::new_legal(board) {
-            n += perft(&board.make_move(&mv), depth - 1);
-        }
-        n
-    }
+// Attacks of a single piece on a square, used by the eval.
+// Sliding pieces are blocked by anything in occupancy.
+
+// Squares a pawn of color on sq attacks
+pub fn pawn_attacks(sq: Square, color: Color) -> BitBoard {
+    pawn::pawn_attacks(sq, color)
+}
+
+pub fn knight_attacks(sq: Square) -> BitBoard {
+    knight::knight_moves(sq)
+}
+
+pub fn king_attacks(sq: Square) -> BitBoard {
+    king::king_moves(sq)
+}
+
+pub fn bishop_attacks(sq: Square, occupancy: BitBoard) -> BitBoard {
+    magic::get_sliding_moves_bb(sq, Piece::Bishop, &occupancy)
+}
+
+pub fn rook_attacks(sq: Square, occupancy: BitBoard) -> BitBoard {
+    magic::get_sliding_moves_bb(sq, Piece::Rook, &occupancy)
+}
+
+pub fn queen_attacks(sq: Square, occupancy: BitBoard) -> BitBoard {
+    magic::get_sliding_moves_bb(sq, Piece::Queen, &occupancy)
 }
 
 #[cfg(test)]
@@ -169,4 +213,124 @@ mod tests {
         legal_iter.set_iterator_mask(attacked & enemy_pieces);
         assert_moves(&board, legal_iter.collect(), "c4f7 h5e5 h5f7 h5h7");
     }
+
+    #[test]
+    fn test_move_kinds() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R2Pp1k/8/6P1/8 b - e3 0 1",
+        ];
+
+        // The generators should agree with working it out from the board
+        for fen in fens.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            for mv in MoveGen::new_legal(&board) {
+                assert_eq!(mv.kind, board.move_kind(&mv), "{} in {}", mv, fen);
+                assert_eq!(mv.is_capture(), board.is_capture(&mv), "{} in {}", mv, fen);
+            }
+        }
+    }
+
+    // Walk the tree and check every gen type against the full move list
+    fn check_gen_types(board: &Board, depth: u16) {
+        let all: Vec<Movement> = MoveGen::new_legal(board).collect();
+        let captures: Vec<Movement> = MoveGen::new(board, GenType::Captures).collect();
+        let quiets: Vec<Movement> = MoveGen::new(board, GenType::Quiets).collect();
+        let fen = board.to_fen();
+
+        assert_eq!(captures.len() + quiets.len(), all.len(), "{}", fen);
+        for mv in all.iter() {
+            assert!(
+                captures.contains(mv) != quiets.contains(mv),
+                "{} in {}",
+                mv,
+                fen
+            );
+        }
+
+        if board.in_check() {
+            let evasions: Vec<Movement> = MoveGen::new(board, GenType::Evasions).collect();
+            assert_eq!(evasions.len(), all.len(), "{}", fen);
+        } else {
+            let checks: Vec<Movement> = MoveGen::new(board, GenType::QuietChecks).collect();
+            let expected: Vec<&Movement> = quiets
+                .iter()
+                .filter(|mv| board.make_move(mv).in_check())
+                .collect();
+            assert_eq!(checks.len(), expected.len(), "{}", fen);
+            for mv in expected {
+                assert!(checks.contains(mv), "{} in {}", mv, fen);
+            }
+        }
+
+        if depth > 1 {
+            for mv in all.iter() {
+                check_gen_types(&board.make_move(mv), depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_types() {
+        let positions = [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                Variant::Standard,
+            ),
+            (
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                Variant::Standard,
+            ),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                Variant::Standard,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                Variant::Standard,
+            ),
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                Variant::Standard,
+            ),
+            (
+                "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R[Nn] w KQkq - 2 3",
+                Variant::Crazyhouse,
+            ),
+            (
+                "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w - - 0 2",
+                Variant::Antichess,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 2+2 0 1",
+                Variant::ThreeCheck,
+            ),
+        ];
+
+        for (fen, variant) in positions.iter() {
+            let mut board = Board::from_fen(fen).unwrap();
+            board.set_variant(*variant);
+            check_gen_types(&board, 3);
+        }
+    }
+
+    #[test]
+    fn test_evasions_en_passant() {
+        // The pawn that just moved is the checker, taking it en passant gets out
+        let board = Board::from_fen("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1").unwrap();
+        let evasions: Vec<Movement> = MoveGen::new(&board, GenType::Evasions).collect();
+        assert_moves(
+            &board,
+            evasions,
+            "e4d3 c5d4 c5b4 c5b5 c5b6 c5c6 c5d6 c5d5 c5c4",
+        );
+    }
+
+    #[test]
+    fn test_quiet_checks_discovered() {
+        let board = Board::from_fen("4k3/8/8/4N3/8/8/8/4RK2 w - - 0 1").unwrap();
+        let checks: Vec<Movement> = MoveGen::new(&board, GenType::QuietChecks).collect();
+        assert_moves(&board, checks, "e5c4 e5c6 e5d3 e5d7 e5f3 e5f7 e5g4 e5g6");
+    }
 }
@@ -0,0 +1,277 @@
+use std::iter::FromIterator;
+use std::ops::Index;
+
+use crate::chess::{MoveKind, Movement, Square};
+
+// More than the most moves of any legal chess position (218). Crazyhouse
+// can go over with a full pocket, the list moves to the heap for those.
+pub const MAX_MOVES: usize = 256;
+
+// Only there to fill the array
+const EMPTY: Entry = Entry {
+    mv: Movement {
+        from_square: Square(0),
+        to_square: Square(0),
+        promote: None,
+        kind: MoveKind::Quiet,
+    },
+    score: 0,
+};
+
+#[derive(Clone)]
+struct Entry {
+    mv: Movement,
+    score: i32,
+}
+
+// Moves on the stack instead of a Vec, so generating doesn't allocate.
+// Each move has a score the search can sort by, zero until it's set.
+#[derive(Clone)]
+pub struct MoveList {
+    entries: [Entry; MAX_MOVES],
+    len: usize,
+    spilled: Option<Vec<Entry>>,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            entries: [EMPTY; MAX_MOVES],
+            len: 0,
+            spilled: None,
+        }
+    }
+
+    #[inline]
+    pub fn push(&mut self, mv: Movement) {
+        let entry = Entry { mv, score: 0 };
+        if let Some(spilled) = &mut self.spilled {
+            spilled.push(entry);
+        } else if self.len < MAX_MOVES {
+            self.entries[self.len] = entry;
+            self.len += 1;
+        } else {
+            let mut spilled = self.entries.to_vec();
+            spilled.push(entry);
+            self.spilled = Some(spilled);
+        }
+    }
+
+    fn entries(&self) -> &[Entry] {
+        match &self.spilled {
+            Some(spilled) => spilled,
+            None => &self.entries[..self.len],
+        }
+    }
+
+    fn entries_mut(&mut self) -> &mut [Entry] {
+        match &mut self.spilled {
+            Some(spilled) => spilled,
+            None => &mut self.entries[..self.len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.spilled = None;
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Movement> {
+        self.entries().get(index).map(|e| &e.mv)
+    }
+
+    pub fn contains(&self, mv: &Movement) -> bool {
+        self.iter().any(|m| m == mv)
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(self.entries().iter())
+    }
+
+    pub fn retain<F: FnMut(&Movement) -> bool>(&mut self, mut keep: F) {
+        if let Some(spilled) = &mut self.spilled {
+            spilled.retain(|e| keep(&e.mv));
+            return;
+        }
+
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(&self.entries[i].mv) {
+                self.entries.swap(kept, i);
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    pub fn score(&self, index: usize) -> i32 {
+        self.entries()[index].score
+    }
+
+    pub fn set_score(&mut self, index: usize, score: i32) {
+        self.entries_mut()[index].score = score;
+    }
+
+    // Set every score with f, then sort best first.
+    pub fn score_and_sort<F: FnMut(&Movement) -> i32>(&mut self, mut f: F) {
+        for entry in self.entries_mut() {
+            entry.score = f(&entry.mv);
+        }
+        self.sort_by_score();
+    }
+
+    // Highest score first. Insertion sort, it's stable and doesn't allocate,
+    // and there are never that many moves.
+    pub fn sort_by_score(&mut self) {
+        let entries = self.entries_mut();
+        for i in 1..entries.len() {
+            let mut j = i;
+            while j > 0 && entries[j - 1].score < entries[j].score {
+                entries.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<usize> for MoveList {
+    type Output = Movement;
+
+    fn index(&self, index: usize) -> &Movement {
+        &self.entries()[index].mv
+    }
+}
+
+impl FromIterator<Movement> for MoveList {
+    fn from_iter<I: IntoIterator<Item = Movement>>(iter: I) -> Self {
+        let mut list = MoveList::new();
+        for mv in iter {
+            list.push(mv);
+        }
+        list
+    }
+}
+
+pub struct IntoIter {
+    list: MoveList,
+    index: usize,
+}
+
+impl Iterator for IntoIter {
+    type Item = Movement;
+
+    fn next(&mut self) -> Option<Movement> {
+        let mv = self.list.get(self.index)?.clone();
+        self.index += 1;
+        Some(mv)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.list.len() - self.index;
+        (left, Some(left))
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Movement;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            list: self,
+            index: 0,
+        }
+    }
+}
+
+pub struct Iter<'a>(std::slice::Iter<'a, Entry>);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Movement;
+
+    fn next(&mut self) -> Option<&'a Movement> {
+        self.0.next().map(|e| &e.mv)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Movement;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::Board;
+    use crate::movegen::MoveGen;
+
+    fn mv(lan: &str) -> Movement {
+        Movement::from_notation(lan).unwrap()
+    }
+
+    #[test]
+    fn test_push_and_iter() {
+        let mut list = MoveList::new();
+        assert!(list.is_empty());
+        list.push(mv("e2e4"));
+        list.push(mv("d2d4"));
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[1], mv("d2d4"));
+        assert!(list.contains(&mv("e2e4")));
+
+        let moves: Vec<Movement> = list.into_iter().collect();
+        assert_eq!(moves, vec![mv("e2e4"), mv("d2d4")]);
+    }
+
+    #[test]
+    fn test_retain_and_sort() {
+        let mut list: MoveList = ["a2a3", "b2b4", "c2c3", "d2d4"]
+            .iter()
+            .map(|s| mv(s))
+            .collect();
+        list.retain(|m| m.from_square != mv("c2c3").from_square);
+        assert_eq!(list.len(), 3);
+
+        // Double pushes first, ties stay in order
+        list.score_and_sort(|m| (m.to_square.rank() == 3) as i32);
+        let moves: Vec<String> = list.iter().map(|m| m.to_notation()).collect();
+        assert_eq!(moves, vec!["b2b4", "d2d4", "a2a3"]);
+        assert_eq!(list.score(0), 1);
+        assert_eq!(list.score(2), 0);
+    }
+
+    #[test]
+    fn test_spill() {
+        // 301 legal moves, more than fits on the stack
+        let mut board = Board::from_fen("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1").unwrap();
+        board.set_variant(crate::chess::Variant::Crazyhouse);
+        let mut list: MoveList = MoveGen::new_legal(&board).collect();
+        assert_eq!(list.len(), 301);
+        assert!(list.contains(&mv("Q@a8")));
+
+        list.retain(|m| !m.is_drop());
+        assert_eq!(list.len(), 5);
+    }
+}
@@ -1,21 +1,24 @@
 use crate::bitboard::BitBoard;
-use crate::chess::{Board, Color, Movement, Piece, Square};
+use crate::chess::{Board, Color, MoveKind, Movement, Piece, Square, Variant, NUM_COLORS};
 
-use super::helpers::{NOT_A_FILE, NOT_H_FILE};
+use super::helpers::{capture_or_quiet, NOT_A_FILE, NOT_H_FILE};
+use super::move_list::MoveList;
+use super::targets::Targets;
 
-// 48 because we don't need the top or bottom rows for pawns
-static mut PAWN_ATTACKS: [[BitBoard; 48]; 64] = [[BitBoard::empty(); 48]; 64];
-static mut PAWN_PUSHES: [[BitBoard; 48]; 64] = [[BitBoard::empty(); 48]; 64];
-static mut PAWN_DBL_PUSHES: [[BitBoard; 48]; 64] = [[BitBoard::empty(); 48]; 64];
+// Pawns are never on the first or last rank, so the pushes there are empty.
+// All worked out at compile time.
+static PAWN_ATTACKS: [[BitBoard; 64]; NUM_COLORS] = gen_pawn_tables().0;
+static PAWN_PUSHES: [[BitBoard; 64]; NUM_COLORS] = gen_pawn_tables().1;
+static PAWN_DBL_PUSHES: [[BitBoard; 64]; NUM_COLORS] = gen_pawn_tables().2;
 
 pub fn pawn_attacks(square: Square, color: Color) -> BitBoard {
-    unsafe { PAWN_ATTACKS[color as usize][(square.0 - 8) as usize] }
+    PAWN_ATTACKS[color as usize][square.0 as usize]
 }
 fn pawn_pushes(square: Square, color: Color) -> BitBoard {
-    unsafe { PAWN_PUSHES[color as usize][(square.0 - 8) as usize] }
+    PAWN_PUSHES[color as usize][square.0 as usize]
 }
 fn pawn_dbl_pushes(square: Square, color: Color) -> BitBoard {
-    unsafe { PAWN_DBL_PUSHES[color as usize][(square.0 - 8) as usize] }
+    PAWN_DBL_PUSHES[color as usize][square.0 as usize]
 }
 
 pub fn get_pawn_attacks(board: &Board, color: Color) -> BitBoard {
@@ -33,45 +36,51 @@ pub fn get_pawn_attacks(board: &Board, color: Color) -> BitBoard {
     attacked
 }
 
-pub fn gen_pawn_moves() {
-    for from_sq_index in 0..48 {
-        let from_sq = Square(from_sq_index + 8);
-        let only_from_sq = 1 << (from_sq_index + 8);
+type PawnTable = [[BitBoard; 64]; NUM_COLORS];
 
-        // Even a fucking gradeschooler would then know
-        let white_pawn_attacks =
+const fn gen_pawn_tables() -> (PawnTable, PawnTable, PawnTable) {
+    let mut attacks = [[BitBoard::empty(); 64]; NUM_COLORS];
+    let mut pushes = [[BitBoard::empty(); 64]; NUM_COLORS];
+    let mut dbl_pushes = [[BitBoard::empty(); 64]; NUM_COLORS];
+
+    let white = Color::White as usize;
+    let black = Color::Black as usize;
+
+    let mut from_sq_index = 0;
+    while from_sq_index < 64 {
+        let only_from_sq: u64 = 1 << from_sq_index;
+        let rank = from_sq_index / 8;
+
+        // Even a fucking gradeschooler would then know. We need these for every
+        // square, to find pawns attacking the back ranks from the other side.
+        attacks[white][from_sq_index] =
             BitBoard(((only_from_sq << 9) & NOT_A_FILE) | ((only_from_sq << 7) & NOT_H_FILE));
-        let black_pawn_attacks =
+        attacks[black][from_sq_index] =
             BitBoard(((only_from_sq >> 9) & NOT_H_FILE) | ((only_from_sq >> 7) & NOT_A_FILE));
 
-        let white_pawn_pushes = BitBoard(only_from_sq << 8);
-        let black_pawn_pushes = BitBoard(only_from_sq >> 8);
-        if from_sq.rank() == 1 {
-            let white_dbl_pawn_pushes = BitBoard(only_from_sq << 16);
-            unsafe {
-                PAWN_DBL_PUSHES[Color::White as usize][from_sq_index as usize] =
-                    white_dbl_pawn_pushes;
-            }
+        if rank == 0 || rank == 7 {
+            from_sq_index += 1;
+            continue;
         }
 
-        if from_sq.rank() == 6 {
-            let black_dbl_pawn_pushes = BitBoard(only_from_sq >> 16);
-            unsafe {
-                PAWN_DBL_PUSHES[Color::Black as usize][from_sq_index as usize] =
-                    black_dbl_pawn_pushes;
-            }
-        }
+        pushes[white][from_sq_index] = BitBoard(only_from_sq << 8);
+        pushes[black][from_sq_index] = BitBoard(only_from_sq >> 8);
 
-        unsafe {
-            PAWN_ATTACKS[Color::White as usize][from_sq_index as usize] = white_pawn_attacks;
-            PAWN_ATTACKS[Color::Black as usize][from_sq_index as usize] = black_pawn_attacks;
-            PAWN_PUSHES[Color::White as usize][from_sq_index as usize] = white_pawn_pushes;
-            PAWN_PUSHES[Color::Black as usize][from_sq_index as usize] = black_pawn_pushes;
+        if rank == 1 {
+            dbl_pushes[white][from_sq_index] = BitBoard(only_from_sq << 16);
+        }
+        if rank == 6 {
+            dbl_pushes[black][from_sq_index] = BitBoard(only_from_sq >> 16);
         }
+
+        from_sq_index += 1;
     }
+
+    (attacks, pushes, dbl_pushes)
 }
 
-pub fn get_pawn_moves(board: &Board, moves: &mut Vec<Movement>, color: Color) {
+pub fn get_pawn_moves(board: &Board, moves: &mut MoveList, targets: &Targets) {
+    let color = targets.color;
     // We need bitwise not because we want the mask to cancel when
     // a piece *IS* there, not when it isn't
     let pushes_mask = !board.combined();
@@ -87,6 +96,19 @@ pub fn get_pawn_moves(board: &Board, moves: &mut Vec<Movement>, color: Color) {
         Color::Black => 0,
     };
 
+    // You can promote to a king in antichess
+    let promotions: &[Piece] = if board.variant == Variant::Antichess {
+        &[
+            Piece::Queen,
+            Piece::Knight,
+            Piece::Rook,
+            Piece::Bishop,
+            Piece::King,
+        ]
+    } else {
+        &[Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop]
+    };
+
     for from_sq in my_pawns {
         let mut moves_bitboard = BitBoard::empty();
 
@@ -108,15 +130,38 @@ pub fn get_pawn_moves(board: &Board, moves: &mut Vec<Movement>, color: Color) {
         };
         moves_bitboard |= dbl_pushes;
 
-        // Add all the moves
+        // Add all the moves, captures and promotions go together
+        let restrict = targets.restrict(Piece::Pawn, from_sq);
         for to_sq in moves_bitboard {
-            if to_sq.rank() == promotion_rank {
-                moves.push(Movement::new(from_sq, to_sq, Some(Piece::Queen)));
-                moves.push(Movement::new(from_sq, to_sq, Some(Piece::Knight)));
-                moves.push(Movement::new(from_sq, to_sq, Some(Piece::Rook)));
-                moves.push(Movement::new(from_sq, to_sq, Some(Piece::Bishop)));
+            let kind = if board.en_passant == Some(to_sq) {
+                MoveKind::EnPassant
+            } else if dbl_pushes.get(to_sq) {
+                MoveKind::DoublePush
+            } else {
+                capture_or_quiet(board, to_sq)
+            };
+
+            let promotion = to_sq.rank() == promotion_rank;
+            let tactical = promotion || matches!(kind, MoveKind::Capture(_) | MoveKind::EnPassant);
+            if (tactical && !targets.gen.captures()) || (!tactical && !targets.gen.quiets()) {
+                continue;
+            }
+
+            let allowed = if kind == MoveKind::EnPassant {
+                targets.en_passant(from_sq, to_sq)
+            } else {
+                restrict.get(to_sq)
+            };
+            if !allowed {
+                continue;
+            }
+
+            if promotion {
+                for &piece in promotions.iter() {
+                    moves.push(Movement::new(from_sq, to_sq, Some(piece)).with_kind(kind));
+                }
             } else {
-                moves.push(Movement::new(from_sq, to_sq, None));
+                moves.push(Movement::new(from_sq, to_sq, None).with_kind(kind));
             }
         }
     }
@@ -196,7 +241,7 @@ mod tests {
         let mut board = Board::from_fen("k1K5/8/8/8/3PPp2/8/8/8 b - e3 0 1").unwrap();
         moves_test(&board, "f4e3 f4f3", "d4d5");
 
-        board.make_move_mut(&Movement::from_notation("f4e3").unwrap());
+        board.make_move_mut(&board.parse_move("f4e3").unwrap());
         board.assert_valid();
         moves_test(&board, "d4d5", "e4e5");
     }
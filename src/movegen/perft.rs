@@ -0,0 +1,188 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+use super::MoveGen;
+use crate::chess::{Board, Movement};
+use crate::zobrist;
+
+// For debugging, used in tests and for a debug command 'go perft depth'
+pub fn perft(board: &Board, depth: u16) -> u64 {
+    if depth == 0 {
+        1
+    } else if depth == 1 {
+        MoveGen::new_legal(board).count() as u64
+    } else {
+        let mut n = 0;
+        for mv in MoveGen::new_legal(board) {
+            n += perft(&board.make_move(&mv), depth - 1);
+        }
+        n
+    }
+}
+
+// Node counts for each root move, in move generator order. The root moves
+// are shared out between threads, and with a table, subtrees we've already
+// counted (transpositions) aren't walked again.
+// Nothing at depth 0, there are no moves to divide the one node between.
+pub fn perft_divide(
+    board: &Board,
+    depth: u16,
+    threads: usize,
+    table: Option<&PerftTable>,
+) -> Vec<(Movement, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    let moves: Vec<Movement> = MoveGen::new_legal(board).collect();
+    if depth == 1 {
+        return moves.into_iter().map(|mv| (mv, 1)).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut counts = vec![0; moves.len()];
+
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= moves.len() {
+                            break;
+                        }
+                        let child = board.make_move(&moves[i]);
+                        let n = match table {
+                            Some(table) => perft_hashed(&child, depth - 1, table),
+                            None => perft(&child, depth - 1),
+                        };
+                        done.push((i, n));
+                    }
+                    done
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            for (i, n) in worker.join().unwrap() {
+                counts[i] = n;
+            }
+        }
+    });
+
+    moves.into_iter().zip(counts).collect()
+}
+
+fn perft_hashed(board: &Board, depth: u16, table: &PerftTable) -> u64 {
+    // Counting the leaves is cheaper than a lookup
+    if depth <= 1 {
+        return perft(board, depth);
+    }
+
+    let key = zobrist::hash(board);
+    if let Some(n) = table.probe(key, depth) {
+        return n;
+    }
+
+    let mut n = 0;
+    for mv in MoveGen::new_legal(board) {
+        n += perft_hashed(&board.make_move(&mv), depth - 1, table);
+    }
+
+    table.store(key, depth, n);
+    n
+}
+
+// Node counts by zobrist key and depth, shared between the perft threads
+// without a lock. Each slot keeps the key xored with the data, so a slot
+// half written by another thread just doesn't match.
+// https://www.chessprogramming.org/Shared_Hash_Table#Lock-less
+pub struct PerftTable {
+    slots: Vec<[AtomicU64; 2]>, // key ^ data, data (nodes << 8 | depth)
+    mask: usize,
+}
+
+impl PerftTable {
+    // Rounded down to a power of two number of slots
+    pub fn new(megabytes: usize) -> PerftTable {
+        let slots = (megabytes.max(1) << 20) / std::mem::size_of::<[AtomicU64; 2]>();
+        let slots = 1 << (usize::BITS - 1 - slots.leading_zeros());
+
+        PerftTable {
+            slots: (0..slots)
+                .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+                .collect(),
+            mask: slots - 1,
+        }
+    }
+
+    fn probe(&self, key: u64, depth: u16) -> Option<u64> {
+        let slot = &self.slots[key as usize & self.mask];
+        let check = slot[0].load(Ordering::Relaxed);
+        let data = slot[1].load(Ordering::Relaxed);
+
+        if check ^ data == key && data & 0xff == depth as u64 && data != 0 {
+            Some(data >> 8)
+        } else {
+            None
+        }
+    }
+
+    // Always replaces, deeper entries aren't worth more here
+    fn store(&self, key: u64, depth: u16, nodes: u64) {
+        let slot = &self.slots[key as usize & self.mask];
+        let data = nodes << 8 | (depth as u64 & 0xff);
+        slot[0].store(key ^ data, Ordering::Relaxed);
+        slot[1].store(data, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn test_perft_divide() {
+        let board = Board::from_fen(KIWIPETE).unwrap();
+        let divide = perft_divide(&board, 3, 4, None);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), 97862);
+
+        // Same order and counts as one at a time
+        for (mv, n) in divide.iter() {
+            assert_eq!(*n, perft(&board.make_move(mv), 2), "{}", mv);
+        }
+    }
+
+    #[test]
+    fn test_depth_0() {
+        let board = Board::from_fen(KIWIPETE).unwrap();
+        assert_eq!(perft(&board, 0), 1);
+        assert!(perft_divide(&board, 0, 2, None).is_empty());
+        assert_eq!(perft_divide(&board, 1, 2, None).len(), 48);
+    }
+
+    #[test]
+    fn test_perft_hashed() {
+        let board = Board::from_fen(KIWIPETE).unwrap();
+        let table = PerftTable::new(4);
+        for _ in 0..2 {
+            let divide = perft_divide(&board, 4, 2, Some(&table));
+            assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), 4085603);
+        }
+    }
+
+    #[test]
+    fn test_perft_table() {
+        let table = PerftTable::new(1);
+        assert_eq!(table.slots.len(), 1 << 16);
+
+        table.store(42, 5, 1234);
+        assert_eq!(table.probe(42, 5), Some(1234));
+        assert_eq!(table.probe(42, 4), None);
+        assert_eq!(table.probe(42 + (1 << 16), 5), None);
+    }
+}
@@ -0,0 +1,142 @@
+use super::{between, line};
+use crate::bitboard::BitBoard;
+use crate::chess::{Board, Color, Piece, Square};
+
+// Which moves to generate. Captures and Quiets split the moves between
+// them, promotions (even non capturing ones) count as captures.
+// Evasions only makes sense in check, and QuietChecks are the quiet
+// moves that give check, directly or by discovery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenType {
+    All,
+    Captures,
+    Quiets,
+    Evasions,
+    QuietChecks,
+}
+
+impl GenType {
+    // Can captures (and promotions) be generated at all?
+    pub fn captures(&self) -> bool {
+        matches!(self, GenType::All | GenType::Captures | GenType::Evasions)
+    }
+
+    pub fn quiets(&self) -> bool {
+        *self != GenType::Captures
+    }
+}
+
+// Where the generators are allowed to move to, worked out once for the
+// whole position.
+pub struct Targets {
+    pub gen: GenType,
+    pub color: Color,
+
+    // By capture or quiet, all the squares not taken by our own pieces
+    // for the gen types that take both
+    allowed: BitBoard,
+
+    // In check, the checker and the squares between it and our king.
+    // Empty in double check, only the king can move then.
+    evasion: BitBoard,
+
+    // Squares each piece would check their king from
+    check_squares: [BitBoard; 6],
+    discoverers: BitBoard,
+    their_king: Option<Square>,
+}
+
+impl Targets {
+    pub fn new(board: &Board, gen: GenType) -> Targets {
+        let color = board.side_to_move;
+        let ours = *board.color_combined(color);
+        let theirs = *board.color_combined(color.other());
+        let occupancy = board.combined();
+
+        let allowed = match gen {
+            GenType::Captures => theirs,
+            GenType::Quiets | GenType::QuietChecks => !occupancy,
+            GenType::All | GenType::Evasions => !ours,
+        };
+
+        let mut evasion = BitBoard::empty();
+        if gen == GenType::Evasions {
+            debug_assert!(board.in_check(), "evasions when not in check");
+            let checkers = board.checkers();
+            if checkers.count_ones() == 1 {
+                let checker = checkers.into_iter().next().unwrap();
+                evasion = checkers | between(board.king(color), checker);
+            }
+        }
+
+        let mut check_squares = [BitBoard::empty(); 6];
+        let mut discoverers = BitBoard::empty();
+        let mut their_king = None;
+        let kings = *board.pieces(Piece::King) & theirs;
+        if gen == GenType::QuietChecks && kings.count_ones() == 1 && board.variant.has_royal_king()
+        {
+            let ksq = board.king(color.other());
+            let bishop = super::bishop_attacks(ksq, occupancy);
+            let rook = super::rook_attacks(ksq, occupancy);
+            check_squares[Piece::Pawn as usize] = super::pawn_attacks(ksq, color.other());
+            check_squares[Piece::Knight as usize] = super::knight_attacks(ksq);
+            check_squares[Piece::Bishop as usize] = bishop;
+            check_squares[Piece::Rook as usize] = rook;
+            check_squares[Piece::Queen as usize] = bishop | rook;
+            discoverers = board.discoverers(color);
+            their_king = Some(ksq);
+        }
+
+        Targets {
+            gen,
+            color,
+            allowed,
+            evasion,
+            check_squares,
+            discoverers,
+            their_king,
+        }
+    }
+
+    // Squares piece on from can go to, leaving out captures or quiets
+    // the gen type doesn't want.
+    pub fn to_squares(&self, piece: Piece, from: Square) -> BitBoard {
+        self.allowed & self.restrict(piece, from)
+    }
+
+    // Just the evasion and check restrictions, for pawns which don't split
+    // captures and quiets by what's on the square.
+    pub fn restrict(&self, piece: Piece, from: Square) -> BitBoard {
+        match self.gen {
+            GenType::Evasions if piece != Piece::King => self.evasion,
+            GenType::QuietChecks => {
+                let mut squares = self.check_squares[piece as usize];
+                if let Some(ksq) = self.their_king {
+                    if self.discoverers.get(from) {
+                        squares |= !line(ksq, from);
+                    }
+                }
+                squares
+            }
+            _ => !BitBoard::empty(),
+        }
+    }
+
+    // En passant gets us out of check when it takes the checking pawn,
+    // which isn't on the square we move to.
+    pub fn en_passant(&self, from: Square, to: Square) -> bool {
+        let captured = Square::new(from.rank(), to.file());
+        self.restrict(Piece::Pawn, from).get(to)
+            || (self.gen == GenType::Evasions && self.evasion.get(captured))
+    }
+
+    // Drops can only go on empty squares anyway
+    pub fn drops(&self, piece: Piece) -> BitBoard {
+        match self.gen {
+            GenType::Captures => BitBoard::empty(),
+            GenType::Evasions => self.evasion,
+            GenType::QuietChecks => self.check_squares[piece as usize],
+            _ => !BitBoard::empty(),
+        }
+    }
+}
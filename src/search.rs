@@ -1,16 +1,30 @@
-use crate::chess::{Board, Movement};
-use crate::eval;
-use crate::movegen::MoveGen;
+use crate::chess::{Board, GameResult, Movement};
+use crate::eval::{self, Accumulator, EvalParams, Network};
+use crate::movegen::{MoveGen, MoveList};
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 const INFINITY: i16 = i16::MAX;
 
+// Aspiration windows: search around the last iteration's score, and widen
+// the window (eventually to infinity) whenever the score falls outside it.
+const ASPIRATION_MIN_DEPTH: i16 = 4;
+const ASPIRATION_WINDOW: i16 = 50;
+const ASPIRATION_MAX_WINDOW: i16 = 1000;
+
+// How often we tell the GUI we're still alive during a long iteration,
+// and how long before we start reporting the current root move.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+const REPORT_NODES_MASK: u64 = 4095; // check the clock every 4096 nodes
+
 #[derive(Debug)]
 pub struct Limits {
     depth: Option<i16>,
     // Maybe could be replaced with wtime, etc.
     thinking_time: Option<Duration>,
+    // Unlike time, this is deterministic (given the same TP).
+    nodes: Option<u64>,
     // TODO: Add other limits, like searchmoves, mate, etc.
 }
 
@@ -19,6 +33,7 @@ impl Limits {
         Self {
             depth: None,
             thinking_time: None,
+            nodes: None,
         }
     }
 }
@@ -30,9 +45,53 @@ pub struct SearchResult {
 
     // Depth of this evaluation, with respect to the root node.
     pub depth: i16,
+
+    // None if eval is exact, otherwise the search failed high or low
+    // and eval is only a bound.
+    pub bound: Option<Bound>,
+}
+
+// Set on a score when the search failed outside its window,
+// in that case the score is only a bound on the real score.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Bound {
+    Lower,
+    Upper,
+}
+
+// A snapshot of the search, handed to the observer after every iteration,
+// and periodically during long ones.
+// Fields we don't know (yet) are None, so frontends can skip them.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct SearchInfo {
+    pub depth: Option<i16>,
+    pub seldepth: Option<i16>,
+    pub score: Option<i16>, // Relative to the side to move
+    pub bound: Option<Bound>,
+    pub nodes: u64,
+    pub nps: u64,
+    pub time: u64, // milliseconds since the search started
+    pub pv: Vec<Movement>,
+    pub hashfull: Option<u16>, // permille
+    pub currmove: Option<Movement>,
+    pub currmovenumber: Option<u16>, // 1 based, like in UCI
+}
+
+// Receives progress from the searcher. The UCI frontend is one observer,
+// tests and tools can use SilentObserver (the default) to keep quiet.
+pub trait SearchObserver: Send {
+    fn info(&mut self, info: &SearchInfo);
+
+    // Free form debugging output, ignored unless an observer cares.
+    fn debug(&mut self, _msg: &str) {}
+}
+
+pub struct SilentObserver;
+
+impl SearchObserver for SilentObserver {
+    fn info(&mut self, _info: &SearchInfo) {}
 }
 
-#[derive(Debug)]
 pub struct Searcher {
     // Transposition table
     // TODO: Store PV and use as move guesses for a/b search
@@ -46,25 +105,27 @@ pub struct Searcher {
 
     // Used so I don't pass fucking everything as a parameter to alphabeta
     start_depth: i16, // start depth of this ID iteration
+    ply: i16,         // distance from the root of the current node
+    seldepth: i16,    // deepest ply reached this iteration, including qs
 
     // Used in should_stop
     limits: Limits,
     start: Instant,
-}
 
-// TODO: Move this to movement?
-fn moves_to_str(moves: &[Movement]) -> String {
-    moves
-        .iter()
-        .map(|mv| mv.to_notation())
-        .collect::<Vec<String>>()
-        .join(" ")
+    observer: Box<dyn SearchObserver>,
+    last_report: Instant,
+
+    params: EvalParams,
+
+    // When set we evaluate with the network instead of the params, the
+    // accumulator stack follows the search down the tree (one per ply).
+    nnue: Option<Arc<Network>>,
+    accumulators: Vec<Accumulator>,
 }
 
 // Sorting is very important for alpha beta search pruning
-fn sort_by_promise(board: &Board, moves: &mut [Movement]) {
-    // negate eval::get_promise because we're sorting lowest to highest
-    moves.sort_by_cached_key(|m| -eval::get_promise(board, m));
+fn sort_by_promise(board: &Board, moves: &mut MoveList) {
+    moves.score_and_sort(|m| eval::get_promise(board, m) as i32);
 }
 
 impl Searcher {
@@ -74,10 +135,17 @@ impl Searcher {
             tp: HashMap::new(),
             tp_max_len: 0,
             start_depth: 0,
+            ply: 0,
+            seldepth: 0,
             limits: Limits::none(),
             start: Instant::now(), // never used, reset in search() before a/b
             fail_high_first: 0,
             fail_high: 0,
+            observer: Box::new(SilentObserver),
+            last_report: Instant::now(),
+            params: EvalParams::default(),
+            nnue: None,
+            accumulators: Vec::new(),
         };
 
         // default to a 64mb hashtable (small)
@@ -86,6 +154,25 @@ impl Searcher {
         s
     }
 
+    pub fn set_observer(&mut self, observer: Box<dyn SearchObserver>) {
+        self.observer = observer;
+    }
+
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+
+    // Scores in the TP were computed with the old params, so they have to go.
+    pub fn set_params(&mut self, params: EvalParams) {
+        self.params = params;
+        self.tp.clear();
+    }
+
+    pub fn set_nnue(&mut self, nnue: Option<Arc<Network>>) {
+        self.nnue = nnue;
+        self.tp.clear();
+    }
+
     pub fn set_hash_size(&mut self, mb: usize) {
         use std::mem;
         self.tp_max_len = (1024 * 1024 * mb) / mem::size_of::<Board>();
@@ -104,6 +191,12 @@ impl Searcher {
         self.search(board, limits)
     }
 
+    pub fn search_nodes(&mut self, board: &Board, nodes: u64) -> SearchResult {
+        let mut limits = Limits::none();
+        limits.nodes = Some(nodes);
+        self.search(board, limits)
+    }
+
     pub fn search(&mut self, board: &Board, limits: Limits) -> SearchResult {
         self.reset_stats();
 
@@ -113,7 +206,8 @@ impl Searcher {
         // NOTE: Tests rely on TP being available after search to verify PV.
         // FIXME: max size can be exeeded during iterative deepening or alphabeta!
         if self.tp.len() > self.tp_max_len {
-            eprintln!("hash clear, len {} max {}", self.tp.len(), self.tp_max_len);
+            let msg = format!("hash clear, len {} max {}", self.tp.len(), self.tp_max_len);
+            self.observer.debug(&msg);
             self.tp.clear();
         }
 
@@ -123,30 +217,73 @@ impl Searcher {
         self.start = Instant::now() - Duration::from_millis(1);
         self.limits = limits;
 
+        self.last_report = self.start;
+
+        self.accumulators.clear();
+        if let Some(nnue) = &self.nnue {
+            self.accumulators.push(nnue.refresh(board));
+        }
+
         let mut depth = 1;
+        let mut prev_score: i16 = 0;
+
+        // What the last iteration that got to finish found
+        let mut completed: Option<SearchResult> = None;
 
         loop {
             self.start_depth = depth;
+            self.seldepth = 0;
+
+            let mut window = ASPIRATION_WINDOW;
+            let (mut alpha, mut beta) = if depth >= ASPIRATION_MIN_DEPTH {
+                (
+                    prev_score.saturating_sub(window),
+                    prev_score.saturating_add(window),
+                )
+            } else {
+                (-INFINITY, INFINITY)
+            };
 
-            self.alphabeta(board, depth, -INFINITY, INFINITY);
-            let nps = (self.nodes as f64 / self.start.elapsed().as_secs_f64()) as u64;
-            let pv = self.get_pv(board);
-
-            let sr = self.tp.get(board).expect("no PV move in TP");
-
-            // NOTE: Maybe we shoulden't print this if alphabeta prematurely exited?
-            // I think its fine though, since we don't update PV on premature exit.
-            // This might signify a depth greater then what we actually searched
-            // though.
-            println!(
-                "info depth {} score cp {} nodes {} nps {} time {} pv {}",
-                depth,
-                sr.eval,
-                self.nodes,
-                nps,
-                self.start.elapsed().as_millis(),
-                moves_to_str(&pv),
-            );
+            let mut finished = false;
+            loop {
+                let score = self.alphabeta(board, depth, alpha, beta);
+                if self.should_stop() {
+                    break;
+                }
+
+                let bound = if score <= alpha {
+                    Bound::Upper
+                } else if score >= beta {
+                    Bound::Lower
+                } else {
+                    prev_score = score;
+                    finished = true;
+                    break;
+                };
+                self.report_iteration(board, depth, score, Some(bound));
+
+                window = window.saturating_mul(2);
+                match bound {
+                    Bound::Upper if window > ASPIRATION_MAX_WINDOW => alpha = -INFINITY,
+                    Bound::Upper => alpha = score.saturating_sub(window),
+                    Bound::Lower if window > ASPIRATION_MAX_WINDOW => beta = INFINITY,
+                    Bound::Lower => beta = score.saturating_add(window),
+                }
+            }
+
+            // Stopped partway through, the root entry is whatever the unfinished
+            // search left there, after a fail low or high maybe only a bound
+            // with a bad move. Stick with the last iteration we finished.
+            if !finished {
+                if let Some(sr) = completed {
+                    return sr;
+                }
+            }
+
+            let sr = self.tp.get(board).expect("no PV move in TP").clone();
+            let score = sr.eval * board.side_to_move.polarize();
+            self.report_iteration(board, depth, score, sr.bound);
+            completed = Some(sr.clone());
             /*
             eprintln!(
                 "move ordering {}/{} = {:.4}",
@@ -158,14 +295,85 @@ impl Searcher {
 
             // Bound ply because of possible recursion limit in endgames.
             if self.should_stop() || depth >= self.limits.depth.unwrap_or(1000) {
-                return sr.clone();
+                return sr;
             }
             depth += 1;
         }
     }
 
+    fn report_iteration(&mut self, board: &Board, depth: i16, score: i16, bound: Option<Bound>) {
+        let info = SearchInfo {
+            depth: Some(depth),
+            seldepth: Some(self.seldepth),
+            score: Some(score),
+            bound,
+            pv: self.get_pv(board),
+            ..self.progress()
+        };
+        self.observer.info(&info);
+        self.last_report = Instant::now();
+    }
+
+    // Called every so often from alphabeta, lets the GUI know we're still alive.
+    fn report_progress(&mut self) {
+        if self.last_report.elapsed() < REPORT_INTERVAL {
+            return;
+        }
+
+        let info = self.progress();
+        self.observer.info(&info);
+        self.last_report = Instant::now();
+    }
+
+    // Once the search has been going for a while, tell the GUI which
+    // root move we're looking at.
+    fn report_currmove(&mut self, mv: &Movement, i: usize) {
+        if self.start.elapsed() < REPORT_INTERVAL {
+            return;
+        }
+
+        let info = SearchInfo {
+            depth: Some(self.start_depth),
+            currmove: Some(mv.clone()),
+            currmovenumber: Some(i as u16 + 1),
+            ..self.progress()
+        };
+        self.observer.info(&info);
+    }
+
+    // The parts of SearchInfo we always know
+    fn progress(&self) -> SearchInfo {
+        let elapsed = self.start.elapsed();
+        SearchInfo {
+            nodes: self.nodes,
+            nps: (self.nodes as f64 / elapsed.as_secs_f64()) as u64,
+            time: elapsed.as_millis() as u64,
+            hashfull: Some(self.hashfull()),
+            ..SearchInfo::default()
+        }
+    }
+
+    // Permille of the TP in use
+    pub fn hashfull(&self) -> u16 {
+        if self.tp_max_len == 0 {
+            return 1000;
+        }
+        usize::min(self.tp.len() * 1000 / self.tp_max_len, 1000) as u16
+    }
+
     // Should a A/B search stop? uses self.limits
     pub fn should_stop(&self) -> bool {
+        // Always finish depth 1, so we have a move to play.
+        if self.start_depth <= 1 {
+            return false;
+        }
+
+        if let Some(nodes) = self.limits.nodes {
+            if self.nodes >= nodes {
+                return true;
+            }
+        }
+
         if let Some(thinking_time) = self.limits.thinking_time {
             self.start.elapsed() > thinking_time
         } else {
@@ -209,19 +417,57 @@ impl Searcher {
         Some(&self.tp.get(board)?.mv)
     }
 
+    // Score of a position that isn't over, relative to the side to move.
+    fn static_eval(&self, board: &Board) -> i16 {
+        match &self.nnue {
+            Some(nnue) => nnue.evaluate(self.accumulators.last().unwrap(), board.side_to_move),
+            None => eval::evaluate(&self.params, board) * board.side_to_move.polarize(),
+        }
+    }
+
+    fn push_move(&mut self, board: &Board, mv: &Movement) {
+        self.ply += 1;
+        if let Some(nnue) = &self.nnue {
+            let acc = nnue.update(self.accumulators.last().unwrap(), board, mv);
+            self.accumulators.push(acc);
+        }
+    }
+
+    fn pop_move(&mut self) {
+        self.ply -= 1;
+        if self.nnue.is_some() {
+            self.accumulators.pop();
+        }
+    }
+
     // alphabeta search in a negamax framework.
     // 'alpha' is always our best score,
     // 'beta' is always our opponent's best possible score
     pub fn alphabeta(&mut self, board: &Board, mut depth: i16, mut alpha: i16, beta: i16) -> i16 {
+        let alpha_orig = alpha;
+
         if self.should_stop() {
             return 0;
         }
 
         self.nodes += 1;
+        self.seldepth = i16::max(self.seldepth, self.ply);
 
-        if let Some(sr) = self.tp.get(board) {
-            if sr.depth >= depth {
-                return sr.eval * board.side_to_move.polarize();
+        if self.nodes & REPORT_NODES_MASK == 0 {
+            self.report_progress();
+        }
+
+        // The root is never cut off by the TP, otherwise re-searching a failed
+        // aspiration window would just return the failed score again.
+        if let Some(sr) = self.tp.get(board).filter(|_| self.ply > 0) {
+            let eval = sr.eval * board.side_to_move.polarize();
+            let usable = match sr.bound {
+                None => true,
+                Some(Bound::Lower) => eval >= beta,
+                Some(Bound::Upper) => eval <= alpha,
+            };
+            if sr.depth >= depth && usable {
+                return eval;
             }
 
             // TODO: Use sr as guess for the best move,
@@ -230,17 +476,17 @@ impl Searcher {
 
         // TODO: Check game over without generating all legal moves (expensive). Since
         // MoveGen lazily checks legality, this would be A LOT more efficent because of pruning.
-        let mut moves: Vec<Movement> = MoveGen::new_legal(board).collect();
+        let mut moves: MoveList = MoveGen::new_legal(board).collect();
         let is_game_over = moves.is_empty();
 
         // NOTE: We don't store the static eval in the TP table, because we aren't whores.
         if is_game_over {
             // Easier to inline instead of calling `eval::get_score`
             // and then have to check if it returned eval::MATE.
-            let score = if board.in_check() {
-                -depth - eval::MATE
-            } else {
-                0
+            let score = match board.game_result() {
+                GameResult::Loss => -depth - eval::MATE,
+                GameResult::Win => depth + eval::MATE,
+                GameResult::Draw => 0,
             };
 
             return score;
@@ -253,7 +499,7 @@ impl Searcher {
 
         if depth < 0 {
             // Quiet search!
-            let score = eval::get_score(board, is_game_over) * board.side_to_move.polarize();
+            let score = self.static_eval(board);
             // It is our move, so if the static score is already better then
             // Our previous best score, we can just return the static eval.
             // FIXME: If we're in zugzwang, then this will prematurely prune.
@@ -261,7 +507,7 @@ impl Searcher {
                 return score;
             }
 
-            moves.retain(|mv| board.is_capture(mv));
+            moves.retain(|mv| mv.is_capture());
 
             if moves.is_empty() {
                 // End of QS, no captures remain
@@ -275,7 +521,13 @@ impl Searcher {
         let mut best_move = moves[0].clone(); // moves len > 0 else gameover and return
 
         for (i, mv) in moves.into_iter().enumerate() {
+            if self.ply == 0 {
+                self.report_currmove(&mv, i);
+            }
+
+            self.push_move(board, &mv);
             let mv_score = -self.alphabeta(&board.make_move(&mv), depth - 1, -beta, -alpha);
+            self.pop_move();
             if mv_score > score {
                 score = mv_score;
                 best_move = mv;
@@ -296,14 +548,24 @@ impl Searcher {
 
         // Storing in TP after stop is too dangerous
         if !self.should_stop() {
-            // Will always be deepest search of this position, since
+            let bound = if score <= alpha_orig {
+                Some(Bound::Upper)
+            } else if score >= beta {
+                Some(Bound::Lower)
+            } else {
+                None
+            };
+
+            // Will usually be the deepest search of this position, since
             // if there was a deeper search already, we would have returned it.
+            // (unless its bound wasn't good enough)
             self.tp.insert(
                 board.clone(),
                 SearchResult {
                     eval: score * board.side_to_move.polarize(),
                     depth,
                     mv: best_move,
+                    bound,
                 },
             );
         }
@@ -320,6 +582,7 @@ impl Default for Searcher {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::uci::moves_to_str;
 
     #[ignore]
     #[test]
@@ -328,7 +591,7 @@ mod tests {
             Board::from_fen("rn1qkbnr/ppp2ppp/3p4/4p2Q/2B1P1b1/8/PPPP1PPP/RNB1K1NR w KQkq - 2 4")
                 .unwrap();
 
-        let mut moves: Vec<Movement> = MoveGen::new_legal(&board).collect();
+        let mut moves: MoveList = MoveGen::new_legal(&board).collect();
         sort_by_promise(&board, &mut moves);
 
         assert_eq!(moves[0], Movement::from_notation("h5f7").unwrap());
@@ -393,6 +656,102 @@ mod tests {
         assert_eq!(moves_to_str(&pv), "e5e2 h2g1 c3c1");
     }
 
+    #[test]
+    fn test_observer_gets_every_depth() {
+        use std::sync::{Arc, Mutex};
+
+        struct Collect(Arc<Mutex<Vec<SearchInfo>>>);
+        impl SearchObserver for Collect {
+            fn info(&mut self, info: &SearchInfo) {
+                self.0.lock().unwrap().push(info.clone());
+            }
+        }
+
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut s = Searcher::new();
+        s.set_observer(Box::new(Collect(infos.clone())));
+        let sr = s.search_depth(&Board::from_start_pos(), 3);
+
+        let infos = infos.lock().unwrap();
+        let depths: Vec<Option<i16>> = infos.iter().map(|i| i.depth).collect();
+        assert_eq!(depths, vec![Some(1), Some(2), Some(3)]);
+
+        let last = infos.last().unwrap();
+        assert_eq!(last.score, Some(sr.eval));
+        assert_eq!(last.pv[0], sr.mv);
+        assert!(last.seldepth.unwrap() >= 3, "seldepth {:?}", last.seldepth);
+        assert!(last.hashfull.is_some());
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let board = Board::from_start_pos();
+
+        let mut a = Searcher::new();
+        let mut b = Searcher::new();
+        let sr_a = a.search_nodes(&board, 5000);
+        let sr_b = b.search_nodes(&board, 5000);
+
+        assert_eq!(a.nodes, 5000);
+        assert_eq!(sr_a, sr_b);
+    }
+
+    #[test]
+    fn test_node_limit_in_research() {
+        use std::sync::{Arc, Mutex};
+
+        struct Collect(Arc<Mutex<Vec<SearchInfo>>>);
+        impl SearchObserver for Collect {
+            fn info(&mut self, info: &SearchInfo) {
+                self.0.lock().unwrap().push(info.clone());
+            }
+        }
+
+        // Fails high at depth 4, then runs out of nodes in the re-search
+        let board = Board::from_fen("8/2p5/8/2kPKp1p/2p4P/2P5/3P4/8 w - - 0 1").unwrap();
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut s = Searcher::new();
+        s.set_observer(Box::new(Collect(infos.clone())));
+        s.search_depth(&board, 4);
+        let fail = infos
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|i| i.bound.is_some())
+            .expect("no aspiration fail")
+            .clone();
+        assert_eq!(fail.depth, Some(4));
+
+        infos.lock().unwrap().clear();
+        let mut s = Searcher::new();
+        s.set_observer(Box::new(Collect(infos.clone())));
+        let sr = s.search_nodes(&board, fail.nodes + 1);
+
+        // The depth 3 result, not the bound from the unfinished depth 4
+        assert_eq!(sr, Searcher::new().search_depth(&board, 3));
+        assert_eq!(sr.bound, None);
+        for info in infos.lock().unwrap().iter() {
+            if info.depth == Some(4) && info.score.is_some() {
+                assert!(info.bound.is_some(), "bounded score reported as exact");
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_with_nnue() {
+        let net = Arc::new(Network::random(7));
+        let mut s = Searcher::new();
+        s.set_nnue(Some(net.clone()));
+
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        let sr = s.search_depth(&board, 3);
+
+        // Even a random net can't miss a mate, and the stack must unwind to the root.
+        assert_eq!(sr.mv.to_notation(), "a1a8");
+        assert_eq!(s.accumulators.len(), 1);
+        assert_eq!(s.static_eval(&board), net.evaluate_board(&board));
+    }
+
     macro_rules! test_think_time {
         ($name:ident, $think_time:expr) => {
             #[test]
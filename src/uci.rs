@@ -1,6 +1,8 @@
 use chess::Board;
 
+use crate::bench;
 use crate::chess;
+use crate::search::{Bound, SearchInfo, SearchObserver};
 use std::str::{FromStr, Split};
 
 #[derive(Debug, PartialEq, Eq)]
@@ -58,12 +60,32 @@ impl Go {
     }
 }
 
+// Not UCI, `bench [depth] [hash] [threads]` like most engines have
+#[derive(Debug, PartialEq, Eq)]
+pub struct Bench {
+    pub depth: i16,
+    pub hash: usize,
+    pub threads: usize,
+}
+
+impl Default for Bench {
+    fn default() -> Self {
+        Bench {
+            depth: bench::DEFAULT_DEPTH,
+            hash: bench::DEFAULT_HASH,
+            threads: bench::DEFAULT_THREADS,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum EngineMessage {
     UCI,
     Debug(bool),
     IsReady,
 
+    SetOption { name: String, value: Option<String> },
+
     UCINewGame,
     Position(Board, Vec<chess::Movement>),
     Go(Go),
@@ -72,17 +94,26 @@ pub enum EngineMessage {
     PonderHit,
     Quit,
 
+    // Not UCI, print the eval of the current position term by term
+    Eval,
+    Bench(Bench),
+
+    // A command we know that didn't make sense, the engine reports it back
+    Invalid(String),
+
     DontMissTheShredderChessAnnualBarbeque, // Very important 10/10
 }
 
-fn get_moves(words: Split<char>) -> Option<Vec<chess::Movement>> {
+fn get_moves(words: Split<char>) -> Result<Vec<chess::Movement>, String> {
     let mut moves = Vec::new();
 
     for word in words {
-        moves.push(chess::Movement::from_notation(word)?);
+        let mv = chess::Movement::from_notation(word)
+            .ok_or_else(|| format!("bad move {} in position command", word))?;
+        moves.push(mv);
     }
 
-    Some(moves)
+    Ok(moves)
 }
 
 pub fn parse(s: &str) -> Option<EngineMessage> {
@@ -97,6 +128,32 @@ pub fn parse(s: &str) -> Option<EngineMessage> {
         },
         "isready" => EngineMessage::IsReady,
 
+        "setoption" => {
+            if words.next()? != "name" {
+                return None;
+            }
+
+            // Both the name and the value can have spaces in them
+            let mut name = Vec::new();
+            let mut value = None;
+            for word in words.by_ref() {
+                if word == "value" {
+                    value = Some(words.collect::<Vec<&str>>().join(" "));
+                    break;
+                }
+                name.push(word);
+            }
+
+            if name.is_empty() {
+                return None;
+            }
+
+            EngineMessage::SetOption {
+                name: name.join(" "),
+                value,
+            }
+        }
+
         "ucinewgame" => EngineMessage::UCINewGame,
         "position" => {
             let board;
@@ -107,7 +164,10 @@ pub fn parse(s: &str) -> Option<EngineMessage> {
                     board = Board::from_start_pos();
 
                     if let Some("moves") = words.next() {
-                        moves = get_moves(words)?;
+                        moves = match get_moves(words) {
+                            Ok(moves) => moves,
+                            Err(e) => return Some(EngineMessage::Invalid(e)),
+                        };
                     }
                 }
 
@@ -123,8 +183,16 @@ pub fn parse(s: &str) -> Option<EngineMessage> {
                         }
                     }
                     let fen = fen.join(" ");
-                    board = Board::from_fen(&fen)?;
-                    moves = get_moves(words)?;
+                    board = match Board::from_fen(&fen) {
+                        Ok(board) => board,
+                        Err(e) => {
+                            return Some(EngineMessage::Invalid(format!("bad fen {}: {}", fen, e)))
+                        }
+                    };
+                    moves = match get_moves(words) {
+                        Ok(moves) => moves,
+                        Err(e) => return Some(EngineMessage::Invalid(e)),
+                    };
                 }
 
                 _ => return None,
@@ -175,6 +243,20 @@ pub fn parse(s: &str) -> Option<EngineMessage> {
         "stop" => EngineMessage::Stop,
         "ponderhit" => EngineMessage::PonderHit,
         "quit" => EngineMessage::Quit,
+        "eval" => EngineMessage::Eval,
+        "bench" => {
+            let mut bench = Bench::default();
+            if let Some(depth) = words.next() {
+                bench.depth = i16::from_str(depth).ok()?;
+            }
+            if let Some(hash) = words.next() {
+                bench.hash = usize::from_str(hash).ok()?;
+            }
+            if let Some(threads) = words.next() {
+                bench.threads = usize::from_str(threads).ok()?;
+            }
+            EngineMessage::Bench(bench)
+        }
 
         "uwu" => EngineMessage::DontMissTheShredderChessAnnualBarbeque,
 
@@ -182,6 +264,75 @@ pub fn parse(s: &str) -> Option<EngineMessage> {
     })
 }
 
+pub fn moves_to_str(moves: &[chess::Movement]) -> String {
+    moves_to_uci(moves, false)
+}
+
+pub fn moves_to_uci(moves: &[chess::Movement], chess960: bool) -> String {
+    moves
+        .iter()
+        .map(|mv| mv.to_uci(chess960))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Format search info as a UCI info line, skipping anything we don't know.
+// With chess960 castling moves are written as king takes rook.
+pub fn format_info(info: &SearchInfo, chess960: bool) -> String {
+    let mut s = String::from("info");
+
+    if let Some(depth) = info.depth {
+        s.push_str(&format!(" depth {}", depth));
+    }
+    if let Some(seldepth) = info.seldepth {
+        s.push_str(&format!(" seldepth {}", seldepth));
+    }
+    if let Some(score) = info.score {
+        s.push_str(&format!(" score cp {}", score));
+
+        match info.bound {
+            Some(Bound::Lower) => s.push_str(" lowerbound"),
+            Some(Bound::Upper) => s.push_str(" upperbound"),
+            None => {}
+        }
+    }
+    if let Some(currmove) = &info.currmove {
+        s.push_str(&format!(" currmove {}", currmove.to_uci(chess960)));
+    }
+    if let Some(currmovenumber) = info.currmovenumber {
+        s.push_str(&format!(" currmovenumber {}", currmovenumber));
+    }
+
+    s.push_str(&format!(
+        " nodes {} nps {} time {}",
+        info.nodes, info.nps, info.time
+    ));
+
+    if let Some(hashfull) = info.hashfull {
+        s.push_str(&format!(" hashfull {}", hashfull));
+    }
+    if !info.pv.is_empty() {
+        s.push_str(&format!(" pv {}", moves_to_uci(&info.pv, chess960)));
+    }
+
+    s
+}
+
+// Prints search info to stdout for the GUI, and debug output to stderr.
+pub struct UciObserver {
+    pub chess960: bool,
+}
+
+impl SearchObserver for UciObserver {
+    fn info(&mut self, info: &SearchInfo) {
+        println!("{}", format_info(info, self.chess960));
+    }
+
+    fn debug(&mut self, msg: &str) {
+        eprintln!("{}", msg);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chess::Movement;
@@ -209,11 +360,103 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_setoption() {
+        assert_eq!(
+            parse("setoption name EvalParams value /tmp/my params.txt"),
+            Some(EngineMessage::SetOption {
+                name: "EvalParams".to_string(),
+                value: Some("/tmp/my params.txt".to_string()),
+            })
+        );
+        assert_eq!(
+            parse("setoption name Clear Hash"),
+            Some(EngineMessage::SetOption {
+                name: "Clear Hash".to_string(),
+                value: None,
+            })
+        );
+        assert_eq!(parse("setoption value 5"), None);
+    }
+
+    #[test]
+    fn test_format_info() {
+        let info = SearchInfo {
+            depth: Some(3),
+            score: Some(-25),
+            nodes: 1000,
+            nps: 20000,
+            time: 50,
+            pv: vec![
+                Movement::from_notation("e2e4").unwrap(),
+                Movement::from_notation("e7e5").unwrap(),
+            ],
+            ..SearchInfo::default()
+        };
+
+        assert_eq!(
+            format_info(&info, false),
+            "info depth 3 score cp -25 nodes 1000 nps 20000 time 50 pv e2e4 e7e5"
+        );
+    }
+
+    #[test]
+    fn test_format_info_extras() {
+        let info = SearchInfo {
+            depth: Some(7),
+            seldepth: Some(12),
+            score: Some(40),
+            bound: Some(Bound::Lower),
+            nodes: 5000,
+            nps: 5000,
+            time: 1000,
+            hashfull: Some(17),
+            ..SearchInfo::default()
+        };
+        assert_eq!(
+            format_info(&info, false),
+            "info depth 7 seldepth 12 score cp 40 lowerbound nodes 5000 nps 5000 time 1000 hashfull 17"
+        );
+
+        let info = SearchInfo {
+            depth: Some(7),
+            currmove: Some(Movement::from_notation("g1f3").unwrap()),
+            currmovenumber: Some(3),
+            nodes: 5000,
+            nps: 5000,
+            time: 1000,
+            ..SearchInfo::default()
+        };
+        assert_eq!(
+            format_info(&info, false),
+            "info depth 7 currmove g1f3 currmovenumber 3 nodes 5000 nps 5000 time 1000"
+        );
+    }
+
     #[test]
     fn test_uci() {
         assert_eq!(parse("uci"), Some(EngineMessage::UCI))
     }
 
+    #[test]
+    fn test_eval() {
+        assert_eq!(parse("eval"), Some(EngineMessage::Eval))
+    }
+
+    #[test]
+    fn test_bench() {
+        assert_eq!(parse("bench"), Some(EngineMessage::Bench(Bench::default())));
+        assert_eq!(
+            parse("bench 6 64 2"),
+            Some(EngineMessage::Bench(Bench {
+                depth: 6,
+                hash: 64,
+                threads: 2
+            }))
+        );
+        assert_eq!(parse("bench deep"), None);
+    }
+
     #[test]
     fn test_position() {
         assert_eq!(
@@ -230,6 +473,16 @@ mod tests {
         );
 
         assert_eq!(parse("position"), None);
+        assert_eq!(
+            parse("position startpos moves e2e4 zz"),
+            Some(EngineMessage::Invalid(
+                "bad move zz in position command".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse("position fen 8/8/8/8 w - - 0 1 moves e2e4"),
+            Some(EngineMessage::Invalid(e)) if e.starts_with("bad fen 8/8/8/8 w - - 0 1")
+        ));
 
         assert_eq!(
             parse("position fen 2k5/2r5/8/3K4/8/8/8/8 b - - 0 1 moves c7c2 d5e5"),
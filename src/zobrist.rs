@@ -1,49 +1,123 @@
-use std::sync::Once;
+use std::sync::OnceLock;
 
 use rand::rngs::StdRng;
 use rand::SeedableRng;
 
 use crate::bitboard::BitBoard;
-use crate::chess::{Board, Square, NUM_COLORS, NUM_PIECES};
+use crate::chess::{Board, CastlingSide, Color, Piece, Square, NUM_COLORS, NUM_PIECES};
 
 const NUM_RANDOMS: usize = NUM_PIECES * NUM_COLORS;
-static mut RANDOMS: [[BitBoard; 64]; NUM_RANDOMS] = [[BitBoard::empty(); 64]; NUM_RANDOMS];
-// static mut EMPTY_RANDOM: BitBoard = BitBoard::empty();
 
-static START: Once = Once::new();
+struct Randoms {
+    pieces: [[BitBoard; 64]; NUM_RANDOMS],
+    side_to_move: BitBoard,
+    castling: [[BitBoard; 8]; 4], // by CastlingSide and rook file
+    en_passant: [BitBoard; 64],
 
-pub fn init_once() {
-    START.call_once(|| {
-        let mut rng = StdRng::from_entropy();
+    // Variant state
+    checks: [[BitBoard; 4]; NUM_COLORS],
+    pockets: [[BitBoard; 64]; NUM_RANDOMS], // by count
+    promoted: [BitBoard; 64],
+}
 
-        #[allow(clippy::needless_range_loop)]
-        for i in 0..NUM_RANDOMS {
-            for j in 0..64 {
-                unsafe {
-                    RANDOMS[i][j] = BitBoard::random(&mut rng);
-                }
-            }
+static RANDOMS: OnceLock<Randoms> = OnceLock::new();
+
+fn gen_randoms() -> Randoms {
+    let mut rng = StdRng::from_entropy();
+    let mut fill = |table: &mut [BitBoard]| {
+        for random in table.iter_mut() {
+            *random = BitBoard::random(&mut rng);
         }
+    };
+
+    let mut randoms = Randoms {
+        pieces: [[BitBoard::empty(); 64]; NUM_RANDOMS],
+        side_to_move: BitBoard::empty(),
+        castling: [[BitBoard::empty(); 8]; 4],
+        en_passant: [BitBoard::empty(); 64],
+        checks: [[BitBoard::empty(); 4]; NUM_COLORS],
+        pockets: [[BitBoard::empty(); 64]; NUM_RANDOMS],
+        promoted: [BitBoard::empty(); 64],
+    };
+
+    for table in randoms.pieces.iter_mut().chain(randoms.pockets.iter_mut()) {
+        fill(table);
+    }
+    for table in randoms.castling.iter_mut() {
+        fill(table);
+    }
+    for table in randoms.checks.iter_mut() {
+        fill(table);
+    }
+    fill(&mut randoms.en_passant);
+    fill(&mut randoms.promoted);
+    fill(std::slice::from_mut(&mut randoms.side_to_move));
+
+    randoms
+}
+
+#[inline]
+fn randoms() -> &'static Randoms {
+    RANDOMS.get_or_init(gen_randoms)
+}
 
-        // unsafe {
-        //     EMPTY_RANDOM = BitBoard::random(&mut rng);
-        // }
-    });
+#[inline]
+fn random(piece: Piece, color: Color, sq: Square) -> BitBoard {
+    randoms().pieces[(piece as usize) * NUM_COLORS + (color as usize)][sq.0 as usize]
 }
 
+// Everything that makes two positions different, so it can key tables
+// (the perft table). Random per run, don't store these anywhere.
 // TODO: Update incrementally on board
 pub fn hash(board: &Board) -> u64 {
+    let randoms = randoms();
     let mut hash = BitBoard(0);
 
-    for i in 0..64 {
-        let piece = board.piece_on(Square(i));
+    for sq in board.combined() {
+        let piece = board.piece_on(sq).unwrap();
+        let color = board.color_on(sq).unwrap();
+        hash ^= random(piece, color, sq);
+    }
 
-        if let Some(piece) = piece {
-            let color = board.color_on(Square(i));
+    if board.side_to_move == Color::Black {
+        hash ^= randoms.side_to_move;
+    }
 
-            if let Some(color) = color {
-                unsafe { hash ^= RANDOMS[(piece as usize) * (color as usize)][i as usize] }
-            }
+    for (i, side) in CastlingSide::ALL.iter().enumerate() {
+        if board.can_castle_unchecked(*side) {
+            hash ^= randoms.castling[i][board.rook_files[i] as usize];
+        }
+    }
+
+    if let Some(sq) = board.en_passant {
+        hash ^= randoms.en_passant[sq.0 as usize];
+    }
+
+    for &color in [Color::White, Color::Black].iter() {
+        let checks = board.checks(color).min(3) as usize;
+        hash ^= randoms.checks[color as usize][checks];
+
+        for piece in 0..NUM_PIECES {
+            let count = board.pockets[color as usize][piece] as usize;
+            hash ^= randoms.pockets[piece * NUM_COLORS + color as usize][count.min(63)];
+        }
+    }
+
+    for sq in board.promoted {
+        hash ^= randoms.promoted[sq.0 as usize];
+    }
+
+    hash.0
+}
+
+// Hash of only the pawns, used to key the pawn structure cache.
+// A board without pawns always hashes to 0.
+pub fn pawn_hash(board: &Board) -> u64 {
+    let mut hash = BitBoard(0);
+    for &color in [Color::White, Color::Black].iter() {
+        let pawns = *board.pieces(Piece::Pawn) & *board.color_combined(color);
+        for sq in pawns {
+            hash ^= random(Piece::Pawn, color, sq);
         }
     }
 
@@ -54,7 +128,7 @@ pub fn hash(board: &Board) -> u64 {
 mod tests {
     use super::*;
     use crate::chess::Board;
-    use crate::movegen::{gen_moves_once, MoveGen};
+    use crate::movegen::MoveGen;
     use std::collections::HashMap;
 
     fn test_zobrist_collisions_hashmap(s: &mut HashMap<u64, Board>, depth: u16, board: &Board) {
@@ -80,15 +154,73 @@ mod tests {
         }
     }
 
+    // Indexing by piece * color used to give every white piece the same
+    // randoms, so swapping one white piece for another kept the hash
+    #[test]
+    fn test_hash_pieces() {
+        let knight = Board::from_fen("4k3/8/8/8/4N3/8/8/4K3 w - - 0 1").unwrap();
+        let bishop = Board::from_fen("4k3/8/8/8/4B3/8/8/4K3 w - - 0 1").unwrap();
+        let black = Board::from_fen("4k3/8/8/8/4n3/8/8/4K3 w - - 0 1").unwrap();
+
+        assert_ne!(hash(&knight), hash(&bishop));
+        assert_ne!(hash(&knight), hash(&black));
+    }
+
+    #[test]
+    fn test_hash_state() {
+        let h = |fen: &str| hash(&Board::from_fen(fen).unwrap());
+        let start = h("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        assert_eq!(
+            start,
+            h("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 5 9")
+        );
+
+        // Same pieces, but not the same position
+        assert_ne!(
+            start,
+            h("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1")
+        );
+        assert_ne!(
+            start,
+            h("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b Kkq - 0 1")
+        );
+        assert_ne!(
+            start,
+            h("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")
+        );
+        assert_ne!(
+            start,
+            h("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR[P] b KQkq - 0 1")
+        );
+        assert_ne!(
+            start,
+            h("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 +1+0")
+        );
+    }
+
+    #[test]
+    fn test_pawn_hash() {
+        let board = Board::from_start_pos();
+        let knight_moved = board.make_move(&board.parse_move("g1f3").unwrap());
+        let pawn_moved = board.make_move(&board.parse_move("e2e4").unwrap());
+
+        assert_eq!(pawn_hash(&board), pawn_hash(&knight_moved));
+        assert_ne!(pawn_hash(&board), pawn_hash(&pawn_moved));
+
+        // Pawns of different colors on the same square
+        let white = Board::from_fen("4k3/8/8/8/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let black = Board::from_fen("4k3/8/8/8/4p3/8/8/4K3 w - - 0 1").unwrap();
+        assert_ne!(pawn_hash(&white), pawn_hash(&black));
+
+        let no_pawns = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(pawn_hash(&no_pawns), 0);
+    }
+
     #[ignore]
     #[test]
     fn test_zobrist_collisions() {
-        init_once();
-        gen_moves_once();
-
         let mut tp = HashMap::new();
 
-        // NOTE: Once you get this to pass, increase ply to 5.
-        test_zobrist_collisions_hashmap(&mut tp, 4, &Board::from_start_pos());
+        test_zobrist_collisions_hashmap(&mut tp, 5, &Board::from_start_pos());
     }
 }
@@ -0,0 +1,303 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use yobmef::{
+    bitboard::BitBoard,
+    chess::{Board, Color, Movement, Piece, Square, Variant},
+    eval::{self, EvalParams},
+    movegen::{self, MoveGen},
+    zobrist,
+};
+
+// Plays random legal games and checks everything that should hold about a
+// board after every move. When a game fails it prints the seed and moves,
+// YOBMEF_SEED=<seed> cargo test --test invariants_test replays only that game.
+
+const GAMES: u64 = 600;
+const MAX_PLIES: usize = 200;
+
+// Seeds go round these, so every start gets the same number of games
+const STARTS: [(Variant, bool, &str); 9] = [
+    (
+        Variant::Standard,
+        false,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    ),
+    (
+        Variant::Standard,
+        false,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    ),
+    (
+        Variant::Standard,
+        true,
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+    ),
+    (
+        Variant::Standard,
+        true,
+        "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+    ),
+    (
+        Variant::ThreeCheck,
+        false,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+    ),
+    (
+        Variant::KingOfTheHill,
+        false,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    ),
+    (
+        Variant::Antichess,
+        false,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+    ),
+    (
+        Variant::Crazyhouse,
+        false,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+    ),
+    (
+        Variant::Crazyhouse,
+        false,
+        "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQK2R[Pp] w KQkq - 0 1",
+    ),
+];
+
+#[test]
+fn test_random_games() {
+    match env::var("YOBMEF_SEED") {
+        Ok(seed) => check_game(seed.parse().expect("YOBMEF_SEED should be a number")),
+        Err(_) => (0..GAMES).for_each(check_game),
+    }
+}
+
+fn check_game(seed: u64) {
+    let (variant, chess960, fen) = STARTS[seed as usize % STARTS.len()];
+    let mut moves = Vec::new();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        play_game(seed, variant, fen, chess960, &mut moves)
+    }));
+
+    if let Err(e) = result {
+        let moves: Vec<String> = moves.iter().map(|mv| mv.to_uci(chess960)).collect();
+        eprintln!(
+            "\n{:?} game with seed {} failed after {} moves\nfen {}\nmoves {}\n\
+             replay with YOBMEF_SEED={} cargo test --test invariants_test\n",
+            variant,
+            seed,
+            moves.len(),
+            fen,
+            moves.join(" "),
+            seed
+        );
+        panic::resume_unwind(e);
+    }
+}
+
+fn play_game(seed: u64, variant: Variant, fen: &str, chess960: bool, moves: &mut Vec<Movement>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut board = Board::from_fen(fen).unwrap();
+    board.set_variant(variant);
+
+    for _ in 0..MAX_PLIES {
+        check_board(&board, chess960);
+
+        let legal: Vec<Movement> = MoveGen::new_legal(&board).collect();
+        let mv = match legal.choose(&mut rng) {
+            Some(mv) => mv.clone(),
+            None => break,
+        };
+
+        check_make_move(&board, &mv);
+        moves.push(mv.clone());
+        board = board.make_move(&mv);
+    }
+}
+
+fn check_board(board: &Board, chess960: bool) {
+    if board.variant.has_royal_king() {
+        board.assert_valid();
+    }
+
+    check_fen(board, chess960);
+    check_attacks(board);
+    check_mirror(board);
+}
+
+// Writing a fen and reading it back gives the same board, and since that
+// board was built from scratch, the same hashes too.
+fn check_fen(board: &Board, chess960: bool) {
+    let fen = board.to_fen();
+    let mut parsed = Board::from_fen(&fen).unwrap();
+    parsed.set_variant(board.variant);
+
+    assert_eq!(&parsed, board, "fen {} doesn't round trip", fen);
+    assert_eq!(parsed.to_fen(), fen);
+    if chess960 {
+        assert_eq!(
+            Board::from_fen(&board.to_shredder_fen()).unwrap().to_fen(),
+            fen
+        );
+    }
+
+    assert_eq!(zobrist::hash(&parsed), zobrist::hash(board), "{}", fen);
+    assert_eq!(
+        zobrist::pawn_hash(&parsed),
+        zobrist::pawn_hash(board),
+        "{}",
+        fen
+    );
+    assert_eq!(std_hash(&parsed), std_hash(board), "{}", fen);
+}
+
+fn std_hash(board: &Board) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    board.hash(&mut hasher);
+    hasher.finish()
+}
+
+// The attack tables and magics against walking the rays one square at a time
+fn check_attacks(board: &Board) {
+    let occupancy = board.combined();
+    let mut attacked = [BitBoard::empty(); 2];
+    let mut attackers = [BitBoard::empty(); 64];
+
+    for from in occupancy {
+        let color = board.color_on(from).unwrap();
+        for to in slow_attacks(board, from) {
+            attacked[color as usize] |= BitBoard::from_square(to);
+            attackers[to.0 as usize] |= BitBoard::from_square(from);
+        }
+    }
+
+    let fen = board.to_fen();
+    for &color in [Color::White, Color::Black].iter() {
+        assert_eq!(
+            board.attacked(color),
+            attacked[color as usize],
+            "{:?} attacks in {}",
+            color,
+            fen
+        );
+    }
+    for sq in (0..64).map(Square) {
+        assert_eq!(
+            board.attackers_to(sq, occupancy),
+            attackers[sq.0 as usize],
+            "attackers to {} in {}",
+            sq,
+            fen
+        );
+    }
+
+    let us = board.side_to_move;
+    let kings = *board.pieces(Piece::King) & *board.color_combined(us);
+    let checkers = if board.variant.has_royal_king() && kings.count_ones() == 1 {
+        attackers[board.king(us).0 as usize] & *board.color_combined(us.other())
+    } else {
+        BitBoard::empty()
+    };
+    assert_eq!(board.checkers(), checkers, "checkers in {}", fen);
+    assert_eq!(board.in_check(), checkers != BitBoard::empty());
+}
+
+fn slow_attacks(board: &Board, from: Square) -> BitBoard {
+    const STRAIGHT: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    const DIAGONAL: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+    const KNIGHT: [(i8, i8); 8] = [
+        (1, 2),
+        (2, 1),
+        (2, -1),
+        (1, -2),
+        (-1, -2),
+        (-2, -1),
+        (-2, 1),
+        (-1, 2),
+    ];
+
+    let forward = board.color_on(from).unwrap().polarize() as i8;
+    let pawn = [(forward, 1), (forward, -1)];
+    let queen: Vec<(i8, i8)> = STRAIGHT.iter().chain(DIAGONAL.iter()).copied().collect();
+
+    let (steps, slides): (&[(i8, i8)], bool) = match board.piece_on(from).unwrap() {
+        Piece::Pawn => (&pawn, false),
+        Piece::Knight => (&KNIGHT, false),
+        Piece::Bishop => (&DIAGONAL, true),
+        Piece::Rook => (&STRAIGHT, true),
+        Piece::Queen => (&queen, true),
+        Piece::King => (&queen, false),
+    };
+
+    let mut attacks = BitBoard::empty();
+    for &(dr, df) in steps {
+        let (mut rank, mut file) = (from.rank() as i8, from.file() as i8);
+        loop {
+            rank += dr;
+            file += df;
+            if !(0..8).contains(&rank) || !(0..8).contains(&file) {
+                break;
+            }
+
+            let sq = Square::new(rank as u8, file as u8);
+            attacks |= BitBoard::from_square(sq);
+            if !slides || board.piece_on(sq).is_some() {
+                break;
+            }
+        }
+    }
+    attacks
+}
+
+// Swapping the colors shouldn't change anything but the sign of the eval
+fn check_mirror(board: &Board) {
+    let mirror = board.mirror();
+    let fen = board.to_fen();
+
+    assert_eq!(&mirror.mirror(), board, "mirror of {}", fen);
+    if board.variant.has_royal_king() {
+        mirror.assert_valid();
+    }
+    assert_eq!(
+        MoveGen::new_legal(&mirror).count(),
+        MoveGen::new_legal(board).count(),
+        "moves in {} and its mirror",
+        fen
+    );
+    assert_eq!(mirror.in_check(), board.in_check());
+    assert_eq!(
+        eval::evaluate(&EvalParams::DEFAULT, &mirror),
+        -eval::evaluate(&EvalParams::DEFAULT, board),
+        "eval of {} and its mirror",
+        fen
+    );
+    assert_eq!(
+        movegen::get_attacked_squares(&mirror, Color::White),
+        movegen::get_attacked_squares(board, Color::Black).flip_vertical()
+    );
+}
+
+// Boards are copied rather than unmade, so the parent has to come out of
+// make_move untouched, and making the move in place has to agree.
+fn check_make_move(board: &Board, mv: &Movement) {
+    let before = board.clone();
+    let child = board.make_move(mv);
+    assert_eq!(board, &before, "{} changed the board it was made on", mv);
+
+    let mut in_place = board.clone();
+    in_place.make_move_mut(mv);
+    assert_eq!(in_place, child, "{} made in place", mv);
+
+    assert!(board.is_legal(mv), "{} generated but not legal", mv);
+    assert_ne!(
+        zobrist::hash(&child),
+        zobrist::hash(board),
+        "{} hashes the same",
+        mv
+    );
+}
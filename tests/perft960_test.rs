@@ -0,0 +1,59 @@
+use yobmef::{chess::Board, movegen::perft};
+
+// Positions from the Chess960 perft suite, in Shredder-FEN
+// https://www.chessprogramming.org/Chess960_Perft_Results
+const POSITIONS: [(&str, [u64; 4]); 7] = [
+    (
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        [21, 528, 12189, 326672],
+    ),
+    (
+        "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        [21, 807, 18002, 667366],
+    ),
+    (
+        "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+        [20, 479, 10471, 273318],
+    ),
+    (
+        "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+        [22, 593, 13440, 382958],
+    ),
+    (
+        "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+        [29, 502, 14569, 287739],
+    ),
+    (
+        "rbbqn1kr/pp2p1pp/6n1/2pp1p2/2P4P/P7/BP1PPPP1/R1BQNNKR w HAha - 0 9",
+        [27, 916, 25798, 890435],
+    ),
+    (
+        "rqbbknr1/1ppp2pp/p5n1/4pp2/P7/1PP5/1Q1PPPPP/R1BBKNRN w GAga - 0 9",
+        [24, 600, 15347, 408207],
+    ),
+];
+
+#[test]
+fn test_perft_960() {
+    for (fen, want) in POSITIONS.iter() {
+        let board = Board::from_fen(fen).unwrap();
+        for (depth, &want) in want.iter().enumerate() {
+            let depth = depth as u16 + 1;
+            let got = perft(&board, depth);
+            assert_eq!(got, want, "perft({}) of {}", depth, fen);
+        }
+    }
+}
+
+// Takes a while, so ignore by default.
+#[ignore]
+#[test]
+fn test_perft_960_5() {
+    let want = [
+        8146062, 16253601, 6417013, 9183776, 8652810, 26302461, 11029596,
+    ];
+    for ((fen, _), &want) in POSITIONS.iter().zip(want.iter()) {
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(perft(&board, 5), want, "perft(5) of {}", fen);
+    }
+}
@@ -0,0 +1,78 @@
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+const PERFT_DEBUG: &str = env!("CARGO_BIN_EXE_perft_debug");
+const YOBMEF: &str = env!("CARGO_BIN_EXE_yobmef");
+
+fn perft_debug(args: &[&str]) -> Output {
+    Command::new(PERFT_DEBUG).args(args).output().unwrap()
+}
+
+fn temp_epd(name: &str) -> String {
+    let path = env::temp_dir().join(format!("{}-{}.epd", name, std::process::id()));
+    path.to_str().unwrap().to_string()
+}
+
+// Our own engine agrees with itself, so nothing to drill into
+#[test]
+fn test_against_engine() {
+    let out = perft_debug(&["--engine", YOBMEF, "--depth", "3"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_against_reference() {
+    let out = perft_debug(&["--reference", "scripts/perft.epd", "--depth", "3"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+#[test]
+fn test_record_then_check() {
+    for depth in ["1", "2"] {
+        let path = temp_epd(&format!("perft-debug-record-{}", depth));
+        let out = perft_debug(&["--engine", YOBMEF, "--depth", depth, "--record", &path]);
+        assert!(out.status.success());
+
+        let out = perft_debug(&["--reference", &path]);
+        fs::remove_file(&path).unwrap();
+        assert!(
+            out.status.success(),
+            "depth {}: {}",
+            depth,
+            String::from_utf8_lossy(&out.stdout)
+        );
+    }
+}
+
+// A wrong count after 1. e4 should be tracked down to that position
+#[test]
+fn test_finds_failing_child() {
+    let path = temp_epd("perft-debug-bad");
+    fs::write(
+        &path,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D2 401\n\
+         rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - ;D1 21\n",
+    )
+    .unwrap();
+
+    let out = perft_debug(&["--reference", &path]);
+    fs::remove_file(&path).unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+
+    assert_eq!(out.status.code(), Some(1), "{}", stdout);
+    assert!(
+        stdout.contains("FAIL rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("depth 1: got 20 want 21"), "{}", stdout);
+}
@@ -1,6 +1,7 @@
+use std::thread;
 use yobmef::{
     chess::Board,
-    movegen::{gen_moves_once, perft},
+    movegen::{perft, perft_divide, PerftTable},
 };
 
 fn test_perft(board: &Board, depth: u16, want: u64) {
@@ -20,27 +21,28 @@ fn test_perft(board: &Board, depth: u16, want: u64) {
 
 #[test]
 fn test_perft_4() {
-    gen_moves_once();
     test_perft(&Board::from_start_pos(), 4, 197281);
 }
 
 #[test]
 fn test_perft_5() {
-    gen_moves_once();
     test_perft(&Board::from_start_pos(), 5, 4865609);
 }
 
-// Takes ~1m to run. so ignore by default.
-#[ignore]
+// Too slow one move at a time, but fine with the perft table
 #[test]
 fn test_perft_6() {
-    gen_moves_once();
-    test_perft(&Board::from_start_pos(), 6, 119060324);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let table = PerftTable::new(64);
+    let got: u64 = perft_divide(&Board::from_start_pos(), 6, threads, Some(&table))
+        .iter()
+        .map(|(_, n)| n)
+        .sum();
+    assert_eq!(got, 119060324);
 }
 
 #[test]
 fn test_perft_4_kiwipete() {
-    gen_moves_once();
     let board =
         Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
             .unwrap();
@@ -49,7 +51,6 @@ fn test_perft_4_kiwipete() {
 
 #[test]
 fn test_perft_5_kiwipete() {
-    gen_moves_once();
     let board =
         Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
             .unwrap();
@@ -0,0 +1,95 @@
+use yobmef::{
+    chess::{Board, Variant},
+    movegen::perft,
+};
+
+fn perft_test(variant: Variant, fen: &str, want: &[u64]) {
+    let mut board = Board::from_fen(fen).unwrap();
+    board.set_variant(variant);
+    for (depth, &want) in want.iter().enumerate() {
+        let depth = depth as u16 + 1;
+        let got = perft(&board, depth);
+        assert_eq!(got, want, "{:?} perft({}) of {}", variant, depth, fen);
+    }
+}
+
+#[test]
+fn test_perft_three_check() {
+    // Nobody can give three checks this early
+    perft_test(
+        Variant::ThreeCheck,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+        &[20, 400, 8902, 197281],
+    );
+
+    // Ra8+ is the third check, so black doesn't get to reply (43 in standard chess)
+    perft_test(
+        Variant::ThreeCheck,
+        "7k/8/8/8/8/8/8/R6K w - - 1+3 0 1",
+        &[16, 41],
+    );
+}
+
+#[test]
+fn test_perft_king_of_the_hill() {
+    perft_test(
+        Variant::KingOfTheHill,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8902, 197281],
+    );
+
+    // Kd4 and Ke4 win on the spot (24 in standard chess)
+    perft_test(
+        Variant::KingOfTheHill,
+        "8/8/8/8/8/3K4/8/7k w - - 0 1",
+        &[8, 18],
+    );
+}
+
+#[test]
+fn test_perft_antichess() {
+    perft_test(
+        Variant::Antichess,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        &[20, 400, 8067, 153299],
+    );
+
+    // Has to take, and can promote to a king
+    perft_test(
+        Variant::Antichess,
+        "8/8/8/8/8/8/1p6/2N4K b - - 0 1",
+        &[5, 15],
+    );
+}
+
+#[test]
+fn test_perft_crazyhouse() {
+    perft_test(
+        Variant::Crazyhouse,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+        &[20, 400, 8902, 197281],
+    );
+
+    // Every kind of drop
+    perft_test(
+        Variant::Crazyhouse,
+        "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1",
+        &[301, 75353],
+    );
+}
+
+// Takes a while, so ignore by default.
+#[ignore]
+#[test]
+fn test_perft_variants_5() {
+    perft_test(
+        Variant::Antichess,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        &[20, 400, 8067, 153299, 2732672],
+    );
+    perft_test(
+        Variant::Crazyhouse,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+        &[20, 400, 8902, 197281, 4888832],
+    );
+}
@@ -1,6 +1,5 @@
 use yobmef::{
     chess::{Board, Movement},
-    movegen::gen_moves_once,
     search::Searcher,
 };
 
@@ -12,7 +11,6 @@ macro_rules! test {
     (name: $name:ident, fen: $fen:expr, want: $want:expr,) => {
         #[test]
         fn $name() {
-            gen_moves_once();
             let board = Board::from_fen($fen).expect("fen should be valid");
             let mut searcher = Searcher::new();
             let search_result = searcher.search_depth(&board, 5);
@@ -27,7 +25,6 @@ macro_rules! test {
     (name: $name:ident, fen: $fen:expr, not: $not:expr,) => {
         #[test]
         fn $name() {
-            gen_moves_once();
             let board = Board::from_fen($fen).expect("fen should be valid");
             let mut searcher = Searcher::new();
             let search_result = searcher.search_depth(&board, 5);